@@ -5,7 +5,8 @@ use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
 pub struct ChannelReaderBuilder{
-    subscriber_builder: SubscriberBuilder
+    subscriber_builder: SubscriberBuilder,
+    auto_follow: bool
 }
 
 #[wasm_bindgen]
@@ -14,7 +15,8 @@ impl ChannelReaderBuilder{
     #[wasm_bindgen(constructor)]
     pub fn new() -> ChannelReaderBuilder{
         ChannelReaderBuilder{
-            subscriber_builder: SubscriberBuilder::new()
+            subscriber_builder: SubscriberBuilder::new(),
+            auto_follow: false
         }
     }
 
@@ -28,8 +30,23 @@ impl ChannelReaderBuilder{
         self
     }
 
+    pub fn psk(mut self, psk_seed: &str) -> ChannelReaderBuilder{
+        self.subscriber_builder = self.subscriber_builder.psk(psk_seed);
+        self
+    }
+
+    pub fn auto_follow(mut self, auto_follow: bool) -> ChannelReaderBuilder{
+        self.auto_follow = auto_follow;
+        self
+    }
+
     pub fn build(self, channel_id: &str, announce_id: &str) -> ChannelReader{
-        let ch = ChRd::new(self.subscriber_builder.build(), channel_id, announce_id);
+        let psks = self.subscriber_builder.psks().to_vec();
+        let mut ch = ChRd::new(self.subscriber_builder.build(), channel_id, announce_id);
+        for psk_seed in psks.iter(){
+            ch.add_psk(psk_seed);
+        }
+        ch.set_auto_follow(self.auto_follow);
         ChannelReader::new(ch)
     }
 }
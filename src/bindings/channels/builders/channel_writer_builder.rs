@@ -0,0 +1,54 @@
+use crate::user_builders::author_builder::AuthorBuilder;
+use crate::bindings::channels::ChannelWriter;
+use crate::channels::ChannelWriter as ChWr;
+use wasm_bindgen::prelude::*;
+use iota_streams::app_channels::api::ChannelType;
+use crate::payload::payload_chunks::DEFAULT_MAX_PAYLOAD_SIZE;
+
+#[wasm_bindgen]
+pub struct ChannelWriterBuilder{
+    author_builder: AuthorBuilder,
+    single_depth: bool,
+    max_payload_size: usize
+}
+
+#[wasm_bindgen]
+impl ChannelWriterBuilder{
+
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ChannelWriterBuilder{
+        ChannelWriterBuilder{
+            author_builder: AuthorBuilder::new(),
+            single_depth: false,
+            max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE
+        }
+    }
+
+    pub fn seed(mut self, seed: &str) -> ChannelWriterBuilder{
+        self.author_builder = self.author_builder.seed(seed);
+        self
+    }
+
+    pub fn node(mut self, node_url: &str) -> ChannelWriterBuilder{
+        self.author_builder = self.author_builder.node(node_url);
+        self
+    }
+
+    pub fn single_depth(mut self) -> ChannelWriterBuilder{
+        self.author_builder = self.author_builder.channel_type(ChannelType::SingleDepth);
+        self.single_depth = true;
+        self
+    }
+
+    pub fn max_payload_size(mut self, max_payload_size: u32) -> ChannelWriterBuilder{
+        self.max_payload_size = max_payload_size as usize;
+        self
+    }
+
+    pub fn build(self) -> ChannelWriter{
+        let mut ch = ChWr::new(self.author_builder.build());
+        ch.set_single_depth(self.single_depth);
+        ch.set_max_payload_size(self.max_payload_size);
+        ChannelWriter::new(ch)
+    }
+}
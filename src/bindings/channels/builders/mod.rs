@@ -1 +1,2 @@
 mod channel_reader_builder;
+mod channel_writer_builder;
@@ -3,8 +3,14 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use wasm_bindgen::prelude::*;
 use crate::utils::set_panic_hook;
-use crate::bindings::channels::{ResponseMessage, KeyNonce, ChannelInfo, EncryptedState};
-use crate::payload::payload_serializers::{RawPacket, RawPacketBuilder};
+use crate::bindings::channels::{ResponseMessage, ObjectMessage, KeyNonce, ChannelInfo, EncryptedState, RecipientKey, JsStateStore, FileMessage};
+use crate::payload::payload_ratchet::KeyRatchet;
+use crate::payload::payload_keystore::{KeyStore, shreddable_key_id};
+use crate::payload::payload_serializers::{RawPacket, RawSerializer, CborPacket, CborSerializer, JsonSerializer, MsgPackSerializer};
+use crate::payload::payload_types::StreamsPacketSerializer;
+use crate::payload::payload_schema::PacketSchema;
+use js_sys::{Array, Map, Object, Reflect, Uint8Array};
+use serde_cbor::Value as CborValue;
 use anyhow::{Result};
 
 
@@ -44,6 +50,21 @@ impl ChannelReader{
         }
     }
 
+    ///
+    /// Send a subscription request to the channel author and get its msg id
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn subscribe(self) -> Result<String, JsValue> {
+        match self.channel.borrow_mut().subscribe().await{
+            Ok(msg_id) => Ok(msg_id),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    pub fn public_key(&self) -> String{
+        self.channel.borrow().public_key()
+    }
+
     ///
     /// Fetch all the remaining msgs
     ///
@@ -54,14 +75,158 @@ impl ChannelReader{
         self.channel.borrow_mut().fetch_raw_msgs().await
     }
 
+    ///
+    /// Fetch directly the msg with the specified index of a single depth channel
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn fetch_msg(self, index: u32, key_nonce: Option<KeyNonce>) -> Result<ResponseMessage, JsValue>{
+        let (msg_id, public, masked) = match self.channel.borrow_mut().fetch_msg(index).await{
+            Ok(Some(res)) => res,
+            Ok(None) => return Err(JsValue::null()),
+            Err(e) => return Err(JsValue::from_str(&e.to_string()))
+        };
+
+        let key_nonce = to_key_nonce(key_nonce);
+        match decode_response_message(&msg_id, &public, || RawPacket::from_streams_response(&public, &masked, &key_nonce)){
+            Ok(res) => Ok(res),
+            Err(_) => Err(JsValue::null())
+        }
+    }
+
+    ///
+    /// Get the next file whose chunks have all been received, taking the file msgs out of the unread msgs.
+    /// It returns null if no file is complete yet
+    ///
+    #[wasm_bindgen(catch)]
+    pub fn receive_file(&self, key_nonce: Option<KeyNonce>) -> Result<Option<FileMessage>, JsValue>{
+        match self.channel.borrow_mut().receive_file(&to_key_nonce(key_nonce)){
+            Ok(file) => Ok(file.map(FileMessage::new)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
     #[wasm_bindgen(catch)]
     pub fn pop_msg(&self, key_nonce: Option<KeyNonce>) -> Result<ResponseMessage, JsValue>{
         let (msg_id, public, masked) = match self.channel.borrow_mut().pop_next_msg(){
-            None => return Err(JsValue::null()),
-            Some(res) => res
+            Ok(None) => return Err(JsValue::null()),
+            Ok(Some(res)) => res,
+            Err(e) => return Err(JsValue::from_str(&e.to_string()))
+        };
+
+        let key_nonce = to_key_nonce(key_nonce);
+        match decode_response_message(&msg_id, &public, || RawPacket::from_streams_response(&public, &masked, &key_nonce)){
+            Ok(res) => Ok(res),
+            Err(_) => Err(JsValue::null())
+        }
+    }
+
+    ///
+    /// Pop the next msg decrypting its masked payload with the key of one of its recipients
+    ///
+    #[wasm_bindgen(catch)]
+    pub fn pop_msg_for_recipient(&self, recipient: &RecipientKey) -> Result<ResponseMessage, JsValue>{
+        let (msg_id, public, masked) = match self.channel.borrow_mut().pop_next_msg(){
+            Ok(None) => return Err(JsValue::null()),
+            Ok(Some(res)) => res,
+            Err(e) => return Err(JsValue::from_str(&e.to_string()))
+        };
+
+        match decode_response_message(&msg_id, &public, || RawPacket::from_streams_response_for_recipient(&public, &masked, recipient.key_ref())){
+            Ok(res) => Ok(res),
+            Err(_) => Err(JsValue::null())
+        }
+    }
+
+    ///
+    /// Pop the next msg decrypting its masked payload with the key derived by the ratchet of the reader
+    ///
+    #[wasm_bindgen(catch)]
+    pub fn pop_ratcheted_msg(&self) -> Result<ResponseMessage, JsValue>{
+        if self.channel.borrow().ratchet().is_none(){
+            return Err(JsValue::from_str("The reader has no ratchet"));
+        }
+        let (msg_id, public, masked) = match self.channel.borrow_mut().pop_next_msg(){
+            Ok(None) => return Err(JsValue::null()),
+            Ok(Some(res)) => res,
+            Err(e) => return Err(JsValue::from_str(&e.to_string()))
         };
 
-        match decode_response_message(&msg_id, &public, &masked, key_nonce){
+        let channel = self.channel.clone();
+        match decode_response_message(&msg_id, &public, || channel.borrow_mut().decode_ratcheted_packet::<RawSerializer>(&public, &masked)){
+            Ok(res) => Ok(res),
+            Err(_) => Err(JsValue::null())
+        }
+    }
+
+    ///
+    /// Pop the next msg decrypting its masked payload with its key in the key store of the reader.
+    /// If the key has been erased the msg is returned with the erased flag set and the masked payload unreadable
+    ///
+    #[wasm_bindgen(catch)]
+    pub fn pop_shreddable_msg(&self) -> Result<ResponseMessage, JsValue>{
+        if self.channel.borrow().key_store().is_none(){
+            return Err(JsValue::from_str("The reader has no key store"));
+        }
+        let (msg_id, public, masked) = match self.channel.borrow_mut().pop_next_msg(){
+            Ok(None) => return Err(JsValue::null()),
+            Ok(Some(res)) => res,
+            Err(e) => return Err(JsValue::from_str(&e.to_string()))
+        };
+
+        let channel = self.channel.borrow();
+        let key_store = match channel.key_store(){
+            None => return Err(JsValue::from_str("The reader has no key store")),
+            Some(key_store) => key_store
+        };
+        // The msgs whose key has been erased are returned flagged as erased, instead of as msgs that failed to decode
+        let erased = match shreddable_key_id(&masked){
+            None => false,
+            Some(key_id) => key_store.key(&key_id).is_none()
+        };
+        match decode_response_message(&msg_id, &public, || RawPacket::from_streams_response_with_key_store(&public, &masked, key_store)){
+            Ok(res) => Ok(res.with_erased(erased)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Set the key store of the per msg keys, exported by the writer
+    ///
+    pub fn set_key_store(&self, key_store: Vec<u8>) -> Result<(), JsValue>{
+        match KeyStore::from_bytes(&key_store){
+            Ok(key_store) => {
+                self.channel.borrow_mut().set_key_store(Some(key_store));
+                Ok(())
+            },
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Set the ratchet, exported by the writer, used to read the masked payloads from its position onward
+    ///
+    pub fn set_ratchet(&self, ratchet: &str) -> Result<(), JsValue>{
+        match KeyRatchet::from_hex(ratchet){
+            Ok(ratchet) => {
+                self.channel.borrow_mut().set_ratchet(Some(ratchet));
+                Ok(())
+            },
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Pop the next msg decoding its CBOR payloads as js objects
+    ///
+    #[wasm_bindgen(catch)]
+    pub fn pop_cbor_msg(&self, key_nonce: Option<KeyNonce>) -> Result<ObjectMessage, JsValue>{
+        let (msg_id, public, masked) = match self.channel.borrow_mut().pop_next_msg(){
+            Ok(None) => return Err(JsValue::null()),
+            Ok(Some(res)) => res,
+            Err(e) => return Err(JsValue::from_str(&e.to_string()))
+        };
+
+        match decode_cbor_message(&msg_id, &public, &masked, key_nonce){
             Ok(res) => Ok(res),
             Err(_) => Err(JsValue::null())
         }
@@ -71,6 +236,59 @@ impl ChannelReader{
         self.channel.borrow().has_next_msg()
     }
 
+    pub fn is_closed(&self) -> bool{
+        self.channel.borrow().is_closed()
+    }
+
+    pub fn successor(&self) -> Option<ChannelInfo>{
+        match self.channel.borrow().successor(){
+            None => None,
+            Some((channel_id, announce_id)) => Some(ChannelInfo::new(&channel_id, &announce_id))
+        }
+    }
+
+    ///
+    /// Attach the Reader to the successor of the closed channel. It returns false if there is no successor
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn follow_successor(self) -> Result<bool, JsValue> {
+        match self.channel.borrow_mut().follow_successor().await{
+            Ok(followed) => Ok(followed),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    pub fn is_revoked(&self) -> bool{
+        self.channel.borrow().is_revoked()
+    }
+
+    ///
+    /// Set the JSON Schemas used to validate the public and masked payloads of the json packets
+    ///
+    pub fn set_schema(&self, public_schema: Option<String>, masked_schema: Option<String>) -> Result<(), JsValue>{
+        match PacketSchema::new(public_schema.as_deref(), masked_schema.as_deref()){
+            Ok(schema) => {
+                self.channel.borrow_mut().set_schema(Some(schema));
+                Ok(())
+            },
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    pub fn is_conforming(&self, msg_id: &str) -> bool{
+        self.channel.borrow().is_conforming(msg_id)
+    }
+
+    pub fn non_conforming_msgs(&self) -> Array{
+        self.channel.borrow().non_conforming_msgs().iter()
+            .map(|msg_id| JsValue::from_str(msg_id))
+            .collect()
+    }
+
+    pub fn msg_branch(&self, msg_id: &str) -> Option<String>{
+        self.channel.borrow().msg_branch(msg_id)
+    }
+
     pub fn channel_address(&self) -> ChannelInfo{
         let (channel_id, announce_id) = self.channel.borrow().channel_address();
         ChannelInfo::new(&channel_id, &announce_id)
@@ -93,6 +311,44 @@ impl ChannelReader{
         }
     }
 
+    pub fn export_to_store(&self, store: &JsStateStore, key: &str, psw: &str) -> Result<(), JsValue>{
+        match self.channel.borrow().export_to_store(&mut store.clone(), key, psw){
+            Ok(_) => Ok(()),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Save the state in the store after each fetch
+    ///
+    pub fn set_autosave(&self, store: &JsStateStore, key: &str, psw: &str){
+        self.channel.borrow_mut().set_autosave(Box::new(store.clone()), key, psw);
+    }
+
+    pub fn disable_autosave(&self){
+        self.channel.borrow_mut().disable_autosave();
+    }
+
+    ///
+    /// Get the error of the last autosave, if it failed
+    ///
+    pub fn last_autosave_error(&self) -> Option<String>{
+        self.channel.borrow().last_autosave_error()
+    }
+
+    #[wasm_bindgen(catch)]
+    pub async fn import_from_store(store: JsStateStore, key: String, psw: String, node_url: Option<String>) -> Result<ChannelReader, JsValue>{
+        let state = match store.load_async(&key).await{
+            Ok(Some(state)) => state,
+            Ok(None) => return Err(JsValue::from_str(&format!("There is no state {} in the store", key))),
+            Err(e) => return Err(JsValue::from_str(&e.to_string()))
+        };
+        match ChRd::import_from_bytes(&state, &psw, node_url.as_deref(), None){
+            Ok(reader) => Ok(ChannelReader::new(reader)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
     pub fn import_from_bytes(state: &EncryptedState, psw: &str, node_url: Option<String>) -> Result<ChannelReader, JsValue>{
         match ChRd::import_from_bytes(state.state(), psw, node_url.as_deref(), None){
             Ok(reader) => Ok(ChannelReader{
@@ -103,20 +359,110 @@ impl ChannelReader{
     }
 }
 
-fn decode_response_message(msg_id: &str, public: &[u8], masked: &[u8], key_nonce: Option<KeyNonce>) -> Result<ResponseMessage>{
-    let key_nonce = match key_nonce{
+fn to_key_nonce(key_nonce: Option<KeyNonce>) -> Option<([u8;32], [u8;24])>{
+    match key_nonce{
         None => None,
         Some(kn) => Some((kn.key_ref().clone(), kn.nonce_ref().clone()))
-    };
+    }
+}
+
+///
+/// Decode a msg, using the masked_packet function to decrypt its masked payload
+///
+fn decode_response_message<F>(msg_id: &str, public: &[u8], masked_packet: F) -> Result<ResponseMessage>
+where
+    F: FnOnce() -> Result<RawPacket>
+{
     let p_packet = RawPacket::from_streams_response(public, public, &None)?;
-    let m_packet = match RawPacket::from_streams_response(public, masked, &key_nonce){
-        Ok(m) => m,
-        Err(_) => RawPacketBuilder::new().public(&"Encrypted".as_bytes().to_vec())?.build()
+    let envelope = p_packet.envelope();
+    let encrypted = || "Encrypted".as_bytes().to_vec();
+
+    // Packets written with the other built-in serializers are returned as json text
+    let (p, m) = match envelope.as_ref().map(|env| env.serializer_id()){
+        Some(JsonSerializer::SERIALIZER_ID) | Some(CborSerializer::SERIALIZER_ID) | Some(MsgPackSerializer::SERIALIZER_ID) => {
+            let p: serde_json::Value = p_packet.deserialize_public_auto()?;
+            let m = match masked_packet()
+                .and_then(|packet| packet.deserialize_masked_auto::<serde_json::Value>()){
+                Ok(m) => serde_json::to_vec(&m)?,
+                Err(_) => encrypted()
+            };
+            (serde_json::to_vec(&p)?, m)
+        }
+        // Packets of custom serializers can't be deserialized here, so their serialized bytes are returned
+        Some(serializer_id) if serializer_id != RawSerializer::SERIALIZER_ID => {
+            let m = match masked_packet(){
+                Ok(packet) if !packet.is_sealed() => packet.masked_bytes().to_vec(),
+                _ => encrypted()
+            };
+            (p_packet.public_bytes().to_vec(), m)
+        }
+        _ => {
+            let p = p_packet.deserialize_public()?;
+            let m = match masked_packet()
+                .and_then(|packet| packet.deserialize_masked()){
+                Ok(m) => m,
+                Err(_) => encrypted()
+            };
+            (p, m)
+        }
     };
-    let p = p_packet.deserialize_public()?;
-    let m = match m_packet.deserialize_masked(){
-        Ok(m) => m,
-        Err(_) => RawPacketBuilder::new().public(&"Encrypted".as_bytes().to_vec())?.build().deserialize_public()?
+    Ok(ResponseMessage::new(msg_id.to_string(), p, m).with_content_type(envelope.map(|env| env.content_type())))
+}
+
+fn decode_cbor_message(msg_id: &str, public: &[u8], masked: &[u8], key_nonce: Option<KeyNonce>) -> Result<ObjectMessage>{
+    let key_nonce = to_key_nonce(key_nonce);
+    let p_packet = CborPacket::from_streams_response(public, public, &None)?;
+    let p = cbor_to_js(&p_packet.deserialize_public::<CborValue>()?);
+
+    let m = match CborPacket::from_streams_response(public, masked, &key_nonce)
+        .and_then(|packet| packet.deserialize_masked::<CborValue>()){
+        Ok(m) => cbor_to_js(&m),
+        Err(_) => JsValue::from_str("Encrypted")
     };
-    Ok(ResponseMessage::new(msg_id.to_string(), p, m))
+    Ok(ObjectMessage::new(msg_id.to_string(), p, m))
+}
+
+///
+/// Convert a CBOR value in a js value. Maps whose keys are all text become objects, the other maps become
+/// js Maps so that integer keys are preserved. Byte strings become Uint8Arrays and the tags are dropped
+///
+fn cbor_to_js(value: &CborValue) -> JsValue{
+    match value{
+        CborValue::Null => JsValue::NULL,
+        CborValue::Bool(b) => JsValue::from_bool(*b),
+        CborValue::Integer(i) => integer_to_js(*i),
+        CborValue::Float(f) => JsValue::from_f64(*f),
+        CborValue::Bytes(bytes) => Uint8Array::from(bytes.as_slice()).into(),
+        CborValue::Text(text) => JsValue::from_str(text),
+        CborValue::Array(values) => values.iter().map(cbor_to_js).collect::<Array>().into(),
+        CborValue::Map(map) if map.keys().all(|key| matches!(key, CborValue::Text(_))) => {
+            let object = Object::new();
+            for (key, value) in map{
+                if let CborValue::Text(key) = key{
+                    let _ = Reflect::set(&object, &JsValue::from_str(key), &cbor_to_js(value));
+                }
+            }
+            object.into()
+        }
+        CborValue::Map(map) => {
+            let js_map = Map::new();
+            for (key, value) in map{
+                js_map.set(&cbor_to_js(key), &cbor_to_js(value));
+            }
+            js_map.into()
+        }
+        CborValue::Tag(_, value) => cbor_to_js(value),
+        _ => JsValue::UNDEFINED
+    }
+}
+
+///
+/// Integers beyond the safe range of the js numbers become strings, so that they keep their precision
+///
+fn integer_to_js(value: i128) -> JsValue{
+    const MAX_SAFE_INTEGER: i128 = (1 << 53) - 1;
+    match (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&value){
+        true => JsValue::from_f64(value as f64),
+        false => JsValue::from_str(&value.to_string())
+    }
 }
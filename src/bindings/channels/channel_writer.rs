@@ -0,0 +1,471 @@
+use crate::channels::ChannelWriter as ChWr;
+use std::rc::Rc;
+use std::cell::RefCell;
+use wasm_bindgen::prelude::*;
+use crate::utils::set_panic_hook;
+use crate::bindings::channels::{KeyNonce, ChannelInfo, EncryptedState, SavedChannel, JsStateStore, FileUpload};
+use js_sys::{Array, Error, Function};
+use crate::payload::payload_schema::PacketSchema;
+use crate::payload::payload_keystore::KeyStore;
+use crate::channels::outbox::RetryOptions;
+use crate::utility::iota_utility::sleep;
+
+
+#[wasm_bindgen]
+pub struct ChannelWriter{
+    channel: Rc<RefCell<ChWr>>
+}
+
+
+impl ChannelWriter {
+
+    pub fn new(channel: ChWr) -> ChannelWriter{
+        set_panic_hook();
+        ChannelWriter{
+            channel: Rc::new(RefCell::new(channel)),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl ChannelWriter{
+
+    pub fn clone(&self) -> ChannelWriter{
+        ChannelWriter{
+            channel: self.channel.clone(),
+        }
+    }
+
+    ///
+    /// Open a channel
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn open(self) -> Result<ChannelInfo, JsValue> {
+        match self.channel.borrow_mut().open().await{
+            Ok((channel_id, announce_id)) => Ok(ChannelInfo::new(&channel_id, &announce_id)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Open a channel and save as first message the encrypted state of the channel itself
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn open_and_save(self, state_psw: String) -> Result<SavedChannel, JsValue> {
+        match self.channel.borrow_mut().open_and_save(&state_psw).await{
+            Ok((channel_id, announce_id, state_msg_id)) => Ok(SavedChannel::new(&channel_id, &announce_id, &state_msg_id)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Publish a checkpoint of the encrypted state of the channel and get its msg id
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn checkpoint(self, state_psw: String) -> Result<String, JsValue> {
+        match self.channel.borrow_mut().checkpoint(&state_psw).await{
+            Ok(msg_id) => Ok(msg_id),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Publish automatically a state checkpoint every `interval` msgs. An interval of 0 disables them.
+    /// The password is not exported, so it must be set again after the channel is restored
+    ///
+    pub fn set_checkpoints(&self, interval: u32, state_psw: &str){
+        self.channel.borrow_mut().set_checkpoints(interval, state_psw);
+    }
+
+    ///
+    /// Write signed packet in a raw format. It returns the id of the sent message
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn send_signed_raw_data(self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<KeyNonce>) -> Result<String, JsValue> {
+        let key_nonce = match key_nonce{
+            None => None,
+            Some(kn) => Some((kn.key_ref().clone(), kn.nonce_ref().clone()))
+        };
+        match self.channel.borrow_mut().send_signed_raw_data(p_data, m_data, key_nonce).await{
+            Ok(msg_id) => Ok(msg_id),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Write signed packet in a raw format, readable only by the specified hex encoded recipient public keys.
+    /// It returns the id of the sent message
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn send_signed_raw_data_for_recipients(self, p_data: Vec<u8>, m_data: Vec<u8>, recipients: Array) -> Result<String, JsValue> {
+        let recipients: Vec<String> = recipients.iter()
+            .filter_map(|r| r.as_string())
+            .collect();
+        match self.channel.borrow_mut().send_signed_raw_data_for_recipients(p_data, m_data, &recipients).await{
+            Ok(msg_id) => Ok(msg_id),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Write signed packet in a raw format, encrypting the masked data with the next key of the ratchet
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn send_signed_raw_data_ratcheted(self, p_data: Vec<u8>, m_data: Vec<u8>) -> Result<String, JsValue> {
+        match self.channel.borrow_mut().send_signed_raw_data_ratcheted(p_data, m_data).await{
+            Ok(msg_id) => Ok(msg_id),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Enable the forward secret ratchet of the masked payloads, seeded from the channel secret
+    ///
+    pub fn set_ratchet(&self, channel_secret: &str){
+        self.channel.borrow_mut().set_ratchet(channel_secret);
+    }
+
+    ///
+    /// Export the ratchet at its current position, to let a reader read the msgs sent from now on
+    ///
+    pub fn export_ratchet(&self) -> Option<String>{
+        self.channel.borrow().export_ratchet()
+    }
+
+    ///
+    /// Write signed packet in a raw format, encrypting the masked data with a new key of the key store
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn send_signed_raw_data_shreddable(self, p_data: Vec<u8>, m_data: Vec<u8>) -> Result<String, JsValue> {
+        match self.channel.borrow_mut().send_signed_raw_data_shreddable(p_data, m_data).await{
+            Ok(msg_id) => Ok(msg_id),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Set the key store of the per msg keys, from bytes exported with export_key_store, or an empty one
+    ///
+    pub fn set_key_store(&self, key_store: Option<Vec<u8>>) -> Result<(), JsValue>{
+        let key_store = match key_store{
+            None => KeyStore::new(),
+            Some(bytes) => match KeyStore::from_bytes(&bytes){
+                Ok(key_store) => key_store,
+                Err(e) => return Err(JsValue::from_str(&e.to_string()))
+            }
+        };
+        self.channel.borrow_mut().set_key_store(Some(key_store));
+        Ok(())
+    }
+
+    pub fn export_key_store(&self) -> Result<Vec<u8>, JsValue>{
+        match self.channel.borrow().key_store().map(|key_store| key_store.to_bytes()){
+            Some(Ok(bytes)) => Ok(bytes),
+            Some(Err(e)) => Err(JsValue::from_str(&e.to_string())),
+            None => Err(JsValue::from_str("The channel has no key store"))
+        }
+    }
+
+    ///
+    /// Erase the key of a msg, making its masked payload permanently unreadable
+    ///
+    pub fn erase_msg_key(&self, msg_id: &str) -> Result<(), JsValue>{
+        match self.channel.borrow_mut().erase_msg_key(msg_id){
+            Ok(_) => Ok(()),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Put a signed packet in a raw format in the outbox, waiting for flush_outbox
+    ///
+    pub fn queue_signed_raw_data(&self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<KeyNonce>) -> Result<(), JsValue> {
+        let key_nonce = match key_nonce{
+            None => None,
+            Some(kn) => Some((kn.key_ref().clone(), kn.nonce_ref().clone()))
+        };
+        match self.channel.borrow_mut().queue_signed_raw_data(p_data, m_data, key_nonce){
+            Ok(_) => Ok(()),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Send the queued packets in order, retrying each one with exponential backoff. It returns the ids of
+    /// the sent msgs followed by an Error for the packet that could not be sent, which remains in the outbox.
+    /// The channel is not borrowed while waiting between the retries, so it can be used in the meantime
+    ///
+    pub async fn flush_outbox(self) -> Array {
+        let results = Array::new();
+        let mut attempt = 0;
+        loop{
+            let res = self.channel.borrow_mut().flush_next().await;
+            match res{
+                None => break,
+                Some(Ok(msg_id)) => {
+                    results.push(&JsValue::from_str(&msg_id));
+                    attempt = 0;
+                }
+                Some(Err(e)) => {
+                    let retry_options = self.channel.borrow().retry_options();
+                    if attempt >= retry_options.max_retries{
+                        results.push(&Error::new(&e.to_string()));
+                        break;
+                    }
+                    sleep(retry_options.delay(attempt)).await;
+                    attempt += 1;
+                }
+            }
+        }
+        results
+    }
+
+    ///
+    /// Set the retry policy of flush_outbox. With queue_on_failure the packets that can't be sent are put
+    /// in the outbox and their send returns an error saying that they have been queued
+    ///
+    pub fn set_retry_options(&self, max_retries: u32, initial_delay_ms: u32, max_delay_ms: u32, queue_on_failure: bool){
+        self.channel.borrow_mut().set_retry_options(RetryOptions{
+            max_retries,
+            initial_delay_ms,
+            max_delay_ms,
+            queue_on_failure
+        });
+    }
+
+    pub fn pending_msgs(&self) -> u32{
+        self.channel.borrow().pending_msgs() as u32
+    }
+
+    ///
+    /// Upload a file, sending its manifest followed by the chunks not confirmed yet. An interrupted upload is
+    /// resumed calling this method again with the same upload. The optional progress callback receives the
+    /// number of confirmed chunks and the total number of chunks. It returns the id of the manifest msg
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn send_file(self, upload: FileUpload, key_nonce: Option<KeyNonce>, progress: Option<Function>) -> Result<String, JsValue> {
+        let key_nonce = match key_nonce{
+            None => None,
+            Some(kn) => Some((kn.key_ref().clone(), kn.nonce_ref().clone()))
+        };
+        let transfer = upload.transfer_ref().clone();
+        loop{
+            // The channel and the upload are not borrowed while the callback runs, so it can use them
+            let res = self.channel.borrow_mut().send_next_file_part(&mut transfer.borrow_mut(), &key_nonce).await;
+            match res{
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => return Err(JsValue::from_str(&e.to_string()))
+            }
+            if let Some(progress) = &progress{
+                let (sent, total) = {
+                    let transfer = transfer.borrow();
+                    (transfer.sent_chunks(), transfer.chunks())
+                };
+                let _ = progress.call2(&JsValue::NULL, &JsValue::from(sent), &JsValue::from(total));
+            }
+        }
+
+        let manifest_msg_id = transfer.borrow().manifest_msg_id();
+        match manifest_msg_id{
+            None => Err(JsValue::from_str("The file manifest has not been sent")),
+            Some(msg_id) => Ok(msg_id)
+        }
+    }
+
+    ///
+    /// Accept the subscription request of a reader. It returns the hex encoded public key of the new subscriber
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn accept_subscriber(self, subscription_id: String) -> Result<String, JsValue> {
+        match self.channel.borrow_mut().accept_subscriber(&subscription_id).await{
+            Ok(subscriber_pk) => Ok(subscriber_pk),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Send a keyload for the specified subscriber public keys. It returns the id of the keyload msg
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn send_keyload(self, subscribers: Array) -> Result<String, JsValue> {
+        let subscribers: Vec<String> = subscribers.iter()
+            .filter_map(|s| s.as_string())
+            .collect();
+        match self.channel.borrow_mut().send_keyload(&subscribers).await{
+            Ok(msg_id) => Ok(msg_id),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Revoke the access of the specified subscriber public keys and pre shared key ids.
+    /// It returns the id of the new keyload msg
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn revoke_access(self, subscribers: Array, pskids: Array) -> Result<String, JsValue> {
+        let subscribers: Vec<String> = subscribers.iter()
+            .filter_map(|s| s.as_string())
+            .collect();
+        let pskids: Vec<String> = pskids.iter()
+            .filter_map(|s| s.as_string())
+            .collect();
+        match self.channel.borrow_mut().revoke_access(&subscribers, &pskids).await{
+            Ok(msg_id) => Ok(msg_id),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    pub fn subscribers(&self) -> Array{
+        self.channel.borrow().subscribers().iter()
+            .map(|s| JsValue::from_str(s))
+            .collect()
+    }
+
+    ///
+    /// Store a pre shared key derived from the seed and get its hex encoded id
+    ///
+    pub fn add_psk(&self, psk_seed: &str) -> String{
+        self.channel.borrow_mut().add_psk(psk_seed)
+    }
+
+    pub fn remove_psk(&self, pskid: &str) -> Result<(), JsValue>{
+        match self.channel.borrow_mut().remove_psk(pskid){
+            Ok(_) => Ok(()),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Send a keyload for the specified pre shared key ids. It returns the id of the keyload msg
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn send_psk_keyload(self, pskids: Array) -> Result<String, JsValue> {
+        let pskids: Vec<String> = pskids.iter()
+            .filter_map(|s| s.as_string())
+            .collect();
+        match self.channel.borrow_mut().send_psk_keyload(&pskids).await{
+            Ok(msg_id) => Ok(msg_id),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Set the JSON Schemas used to validate the public and masked payloads of the json packets before sending them
+    ///
+    pub fn set_schema(&self, public_schema: Option<String>, masked_schema: Option<String>) -> Result<(), JsValue>{
+        match PacketSchema::new(public_schema.as_deref(), masked_schema.as_deref()){
+            Ok(schema) => {
+                self.channel.borrow_mut().set_schema(Some(schema));
+                Ok(())
+            },
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Publish the schema of the channel and get the id of the schema msg
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn announce_schema(self) -> Result<String, JsValue> {
+        match self.channel.borrow_mut().announce_schema().await{
+            Ok(msg_id) => Ok(msg_id),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Close the channel, optionally pointing to a successor channel. It returns the id of the end of stream msg
+    ///
+    #[wasm_bindgen(catch)]
+    pub async fn close(self, successor: Option<ChannelInfo>) -> Result<String, JsValue> {
+        let successor = match successor{
+            None => None,
+            Some(info) => Some((info.channel_id(), info.announce_id()))
+        };
+        match self.channel.borrow_mut().close(successor).await{
+            Ok(msg_id) => Ok(msg_id),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    pub fn is_closed(&self) -> bool{
+        self.channel.borrow().is_closed()
+    }
+
+    pub fn channel_address(&self) -> ChannelInfo{
+        let (channel_id, announce_id) = self.channel.borrow().channel_address();
+        ChannelInfo::new(&channel_id, &announce_id)
+    }
+
+    ///
+    /// Get the index of msg to find the transaction on the tangle
+    ///
+    pub fn msg_index(&self, msg_id: &str) -> Result<String, JsValue>{
+        match self.channel.borrow().msg_index(msg_id){
+            Ok(index) => Ok(index),
+            Err(_) => Err(JsValue::null())
+        }
+    }
+
+    pub fn export_to_bytes(&self, psw: &str) -> Result<EncryptedState, JsValue>{
+        match self.channel.borrow().export_to_bytes(psw){
+            Ok(state) => Ok(EncryptedState::new(state)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    pub fn export_to_store(&self, store: &JsStateStore, key: &str, psw: &str) -> Result<(), JsValue>{
+        match self.channel.borrow().export_to_store(&mut store.clone(), key, psw){
+            Ok(_) => Ok(()),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    ///
+    /// Save the state in the store after each sent msg
+    ///
+    pub fn set_autosave(&self, store: &JsStateStore, key: &str, psw: &str){
+        self.channel.borrow_mut().set_autosave(Box::new(store.clone()), key, psw);
+    }
+
+    pub fn disable_autosave(&self){
+        self.channel.borrow_mut().disable_autosave();
+    }
+
+    ///
+    /// Get the error of the last autosave, if it failed
+    ///
+    pub fn last_autosave_error(&self) -> Option<String>{
+        self.channel.borrow().last_autosave_error()
+    }
+
+    #[wasm_bindgen(catch)]
+    pub async fn import_from_store(store: JsStateStore, key: String, psw: String, node_url: Option<String>) -> Result<ChannelWriter, JsValue>{
+        let state = match store.load_async(&key).await{
+            Ok(Some(state)) => state,
+            Ok(None) => return Err(JsValue::from_str(&format!("There is no state {} in the store", key))),
+            Err(e) => return Err(JsValue::from_str(&e.to_string()))
+        };
+        match ChWr::import_from_bytes(&state, &psw, node_url.as_deref(), None).await{
+            Ok(writer) => Ok(ChannelWriter::new(writer)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    #[wasm_bindgen(catch)]
+    pub async fn import_from_bytes(state: EncryptedState, psw: String, node_url: Option<String>) -> Result<ChannelWriter, JsValue>{
+        match ChWr::import_from_bytes(state.state(), &psw, node_url.as_deref(), None).await{
+            Ok(writer) => Ok(ChannelWriter::new(writer)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    #[wasm_bindgen(catch)]
+    pub async fn import_from_tangle(channel_id: String, announce_id: String, state_psw: String, node_url: Option<String>) -> Result<ChannelWriter, JsValue>{
+        match ChWr::import_from_tangle(&channel_id, &announce_id, &state_psw, node_url.as_deref(), None).await{
+            Ok(writer) => Ok(ChannelWriter::new(writer)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+}
@@ -1,10 +1,19 @@
 mod channel_reader;
 pub use channel_reader::ChannelReader;
+mod channel_writer;
+pub use channel_writer::ChannelWriter;
+mod state_store;
+pub use state_store::JsStateStore;
 
 pub mod builders;
 
 use wasm_bindgen::prelude::*;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::payload::payload_files::{FileTransfer, ReceivedFile};
 use crate::utility::iota_utility::{create_encryption_key, create_encryption_nonce};
+use crate::payload::payload_recipients::RecipientKey as RcpKey;
 use std::convert::TryInto;
 
 #[wasm_bindgen]
@@ -23,12 +32,24 @@ impl EncryptedState{
 pub struct ResponseMessage{
     msg_id: String,
     public: Vec<u8>,
-    masked: Vec<u8>
+    masked: Vec<u8>,
+    content_type: Option<String>,
+    erased: bool
 }
 
 impl ResponseMessage{
     pub fn new(msg_id: String, public: Vec<u8>, masked: Vec<u8>) -> Self {
-        ResponseMessage { msg_id, public, masked }
+        ResponseMessage { msg_id, public, masked, content_type: None, erased: false }
+    }
+
+    pub fn with_content_type(mut self, content_type: Option<String>) -> Self {
+        self.content_type = content_type;
+        self
+    }
+
+    pub fn with_erased(mut self, erased: bool) -> Self {
+        self.erased = erased;
+        self
     }
 }
 
@@ -46,6 +67,46 @@ impl ResponseMessage{
     pub fn masked(&self) -> Vec<u8>{
         self.masked.clone()
     }
+    #[wasm_bindgen(getter)]
+    pub fn content_type(&self) -> Option<String>{
+        self.content_type.clone()
+    }
+    ///
+    /// True if the key of the masked payload has been erased from the key store, so it can't be read anymore
+    ///
+    #[wasm_bindgen(getter)]
+    pub fn erased(&self) -> bool{
+        self.erased
+    }
+}
+
+#[wasm_bindgen]
+pub struct ObjectMessage{
+    msg_id: String,
+    public: JsValue,
+    masked: JsValue
+}
+
+impl ObjectMessage{
+    pub fn new(msg_id: String, public: JsValue, masked: JsValue) -> Self {
+        ObjectMessage { msg_id, public, masked }
+    }
+}
+
+#[wasm_bindgen]
+impl ObjectMessage{
+    #[wasm_bindgen(getter)]
+    pub fn msg_id(&self) -> String {
+        self.msg_id.to_string()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn public(&self) -> JsValue {
+        self.public.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn masked(&self) -> JsValue{
+        self.masked.clone()
+    }
 }
 
 #[wasm_bindgen]
@@ -71,6 +132,78 @@ impl ChannelInfo{
     }
 }
 
+#[wasm_bindgen]
+pub struct SavedChannel{
+    channel_id: String,
+    announce_id: String,
+    state_msg_id: String
+}
+
+impl SavedChannel{
+    pub fn new(channel_id: &str, announce_id: &str, state_msg_id: &str) -> SavedChannel{
+        SavedChannel{
+            channel_id: channel_id.to_string(),
+            announce_id: announce_id.to_string(),
+            state_msg_id: state_msg_id.to_string()
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl SavedChannel{
+    pub fn channel_info(&self) -> ChannelInfo {
+        ChannelInfo::new(&self.channel_id, &self.announce_id)
+    }
+    pub fn state_msg_id(&self) -> String {
+        self.state_msg_id.clone()
+    }
+}
+
+///
+/// X25519 key pair used to read the masked payloads encrypted for a list of recipients
+///
+#[wasm_bindgen]
+pub struct RecipientKey{
+    key: RcpKey
+}
+
+#[wasm_bindgen]
+impl RecipientKey{
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> RecipientKey{
+        RecipientKey{
+            key: RcpKey::generate()
+        }
+    }
+
+    pub fn from_hex(secret_key: &str) -> Result<RecipientKey, JsValue>{
+        match RcpKey::from_hex(secret_key){
+            Ok(key) => Ok(RecipientKey{ key }),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn public_key(&self) -> String {
+        self.key.public_key_hex()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn secret_key(&self) -> String {
+        self.key.to_hex()
+    }
+}
+
+impl RecipientKey{
+    pub fn key_ref(&self) -> &RcpKey {
+        &self.key
+    }
+}
+
+///
+/// Key used to encrypt the masked payloads. A random nonce is generated for each msg,
+/// the nonce derived here is needed only to read the msgs encrypted with a fixed nonce
+///
 #[wasm_bindgen]
 pub struct KeyNonce{
     key: [u8; 32],
@@ -114,3 +247,119 @@ impl KeyNonce{
         &self.nonce
     }
 }
+
+///
+/// Upload of a file in a channel. It keeps track of the confirmed chunks, so an interrupted upload
+/// can be resumed sending it again. It can be exported with to_bytes to survive restarts
+///
+#[wasm_bindgen]
+pub struct FileUpload{
+    transfer: Rc<RefCell<FileTransfer>>
+}
+
+#[wasm_bindgen]
+impl FileUpload{
+    ///
+    /// Create the upload of the data of a file. The metadata is an optional object of string values
+    ///
+    #[wasm_bindgen(constructor)]
+    pub fn new(name: &str, data: Vec<u8>, metadata: JsValue) -> Result<FileUpload, JsValue>{
+        let metadata: HashMap<String, String> = match metadata.is_null() || metadata.is_undefined(){
+            true => HashMap::new(),
+            false => match metadata.into_serde(){
+                Ok(metadata) => metadata,
+                Err(e) => return Err(JsValue::from_str(&e.to_string()))
+            }
+        };
+        Ok(FileUpload::from_transfer(FileTransfer::new(name, &data, metadata)))
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<FileUpload, JsValue>{
+        match FileTransfer::from_bytes(&bytes){
+            Ok(transfer) => Ok(FileUpload::from_transfer(transfer)),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue>{
+        match bincode::serialize(&*self.transfer.borrow()){
+            Ok(bytes) => Ok(bytes),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+
+    pub fn clone(&self) -> FileUpload{
+        FileUpload{
+            transfer: self.transfer.clone()
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn transfer_id(&self) -> String {
+        self.transfer.borrow().transfer_id()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn chunks(&self) -> u32 {
+        self.transfer.borrow().chunks()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn sent_chunks(&self) -> u32 {
+        self.transfer.borrow().sent_chunks()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn manifest_msg_id(&self) -> Option<String> {
+        self.transfer.borrow().manifest_msg_id()
+    }
+    pub fn is_completed(&self) -> bool {
+        self.transfer.borrow().is_completed()
+    }
+}
+
+impl FileUpload{
+    pub fn from_transfer(transfer: FileTransfer) -> FileUpload{
+        FileUpload{
+            transfer: Rc::new(RefCell::new(transfer))
+        }
+    }
+
+    pub fn transfer_ref(&self) -> &Rc<RefCell<FileTransfer>> {
+        &self.transfer
+    }
+}
+
+///
+/// File received from a channel, after the verification of its content hash
+///
+#[wasm_bindgen]
+pub struct FileMessage{
+    file: ReceivedFile
+}
+
+impl FileMessage{
+    pub fn new(file: ReceivedFile) -> Self {
+        FileMessage{ file }
+    }
+}
+
+#[wasm_bindgen]
+impl FileMessage{
+    #[wasm_bindgen(getter)]
+    pub fn transfer_id(&self) -> String {
+        self.file.transfer_id()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.file.name()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn metadata(&self) -> Result<JsValue, JsValue> {
+        match JsValue::from_serde(&self.file.metadata()){
+            Ok(metadata) => Ok(metadata),
+            Err(e) => Err(JsValue::from_str(&e.to_string()))
+        }
+    }
+    #[wasm_bindgen(getter)]
+    pub fn data(&self) -> Vec<u8> {
+        self.file.data().to_vec()
+    }
+}
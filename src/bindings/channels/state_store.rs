@@ -0,0 +1,140 @@
+use crate::channels::state_store::StateStore;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+use js_sys::{Array, Function, Promise, Uint8Array};
+use anyhow::Result;
+
+///
+/// State store backed by js callbacks:
+/// load(key) returning an Uint8Array or null, save(key, state), delete(key) and list() returning an Array of keys.
+/// save and delete can return a Promise: the calls are chained, so each one starts after the previous one
+/// has completed, and the outcome of the last completed call is kept as the error of the store.
+/// load can return a Promise only when the store is used by the async imports
+///
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct JsStateStore{
+    load_fn: Function,
+    save_fn: Function,
+    delete_fn: Function,
+    list_fn: Function,
+    last_op: Rc<RefCell<Option<Promise>>>,
+    last_error: Rc<RefCell<Option<String>>>
+}
+
+#[wasm_bindgen]
+impl JsStateStore{
+    #[wasm_bindgen(constructor)]
+    pub fn new(load: Function, save: Function, delete: Function, list: Function) -> JsStateStore{
+        JsStateStore{
+            load_fn: load,
+            save_fn: save,
+            delete_fn: delete,
+            list_fn: list,
+            last_op: Rc::new(RefCell::new(None)),
+            last_error: Rc::new(RefCell::new(None))
+        }
+    }
+
+    ///
+    /// Get the error of the last completed save or delete, if it failed
+    ///
+    pub fn last_error(&self) -> Option<String>{
+        self.last_error.borrow().clone()
+    }
+}
+
+impl JsStateStore{
+    ///
+    /// Load the state with the specified key, waiting for the Promise returned by the load callback, if any
+    ///
+    pub async fn load_async(&self, key: &str) -> Result<Option<Vec<u8>>>{
+        // The state is loaded after the pending saves, so the last saved one is returned
+        let last_op = self.last_op.borrow().clone();
+        if let Some(last_op) = last_op{
+            let _ = JsFuture::from(last_op).await;
+        }
+        let mut res = js_result(self.load_fn.call1(&JsValue::NULL, &JsValue::from_str(key)))?;
+        if let Some(promise) = res.dyn_ref::<Promise>(){
+            res = js_result(JsFuture::from(promise.clone()).await)?;
+        }
+        Ok(to_state(res))
+    }
+
+    ///
+    /// Run the call after the previous save or delete has completed, waiting for the Promise it returns, if any.
+    /// The outcome of the call replaces the error of the store, so each failure is reported by its own call
+    ///
+    fn chain(&self, call: impl FnOnce() -> std::result::Result<JsValue, JsValue> + 'static){
+        let previous = self.last_op.borrow_mut().take();
+        let last_error = self.last_error.clone();
+        let op = future_to_promise(async move {
+            if let Some(previous) = previous{
+                let _ = JsFuture::from(previous).await;
+            }
+            let res = match js_result(call()){
+                Ok(res) => match res.dyn_into::<Promise>(){
+                    Ok(promise) => js_result(JsFuture::from(promise).await),
+                    Err(res) => Ok(res)
+                },
+                Err(e) => Err(e)
+            };
+            *last_error.borrow_mut() = res.err().map(|e| e.to_string());
+            Ok(JsValue::NULL)
+        });
+        *self.last_op.borrow_mut() = Some(op);
+    }
+}
+
+impl StateStore for JsStateStore{
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let res = js_result(self.load_fn.call1(&JsValue::NULL, &JsValue::from_str(key)))?;
+        if res.is_instance_of::<Promise>(){
+            return Err(anyhow::Error::msg("The load callback returned a Promise, use the async import"));
+        }
+        Ok(to_state(res))
+    }
+
+    fn save(&mut self, key: &str, state: &[u8]) -> Result<()> {
+        let (save_fn, key, state) = (self.save_fn.clone(), JsValue::from_str(key), Uint8Array::from(state));
+        self.chain(move || save_fn.call2(&JsValue::NULL, &key, &state));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<()> {
+        let (delete_fn, key) = (self.delete_fn.clone(), JsValue::from_str(key));
+        self.chain(move || delete_fn.call1(&JsValue::NULL, &key));
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let res = js_result(self.list_fn.call0(&JsValue::NULL))?;
+        if res.is_instance_of::<Promise>(){
+            return Err(anyhow::Error::msg("The list callback returned a Promise, which is not supported"));
+        }
+        Ok(Array::from(&res).iter()
+            .filter_map(|key| key.as_string())
+            .collect())
+    }
+
+    fn last_error(&self) -> Option<String> {
+        self.last_error.borrow().clone()
+    }
+}
+
+fn to_state(res: JsValue) -> Option<Vec<u8>>{
+    if res.is_null() || res.is_undefined(){
+        return None;
+    }
+    Some(Uint8Array::new(&res).to_vec())
+}
+
+fn js_result(res: std::result::Result<JsValue, JsValue>) -> Result<JsValue>{
+    match res{
+        Ok(value) => Ok(value),
+        Err(e) => Err(anyhow::Error::msg(e.as_string().unwrap_or_else(|| "State store error".to_string())))
+    }
+}
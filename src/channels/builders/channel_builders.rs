@@ -2,17 +2,35 @@ use crate::user_builders::author_builder::AuthorBuilder;
 use iota_streams::app::transport::tangle::client::SendOptions;
 use crate::channels::{ChannelReader, ChannelWriter};
 use crate::user_builders::subscriber_builder::SubscriberBuilder;
+use iota_streams::app_channels::api::ChannelType;
+use crate::channels::outbox::RetryOptions;
+use crate::payload::payload_chunks::DEFAULT_MAX_PAYLOAD_SIZE;
+use crate::payload::payload_schema::PacketSchema;
+use crate::payload::payload_ratchet::KeyRatchet;
+use crate::payload::payload_keystore::KeyStore;
 
 
 pub struct ChannelWriterBuilder{
-    author_builder: AuthorBuilder
+    author_builder: AuthorBuilder,
+    single_depth: bool,
+    retry_options: RetryOptions,
+    checkpoints: Option<(u32, String)>,
+    max_payload_size: usize,
+    schema: Option<PacketSchema>,
+    key_store: Option<KeyStore>
 }
 
 impl ChannelWriterBuilder{
 
     pub fn new() -> ChannelWriterBuilder{
         ChannelWriterBuilder{
-            author_builder: AuthorBuilder::new()
+            author_builder: AuthorBuilder::new(),
+            single_depth: false,
+            retry_options: RetryOptions::default(),
+            checkpoints: None,
+            max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE,
+            schema: None,
+            key_store: None
         }
     }
 
@@ -31,21 +49,69 @@ impl ChannelWriterBuilder{
         self
     }
 
+    pub fn channel_type(mut self, channel_type: ChannelType) -> Self{
+        self.single_depth = matches!(channel_type, ChannelType::SingleDepth);
+        self.author_builder = self.author_builder.channel_type(channel_type);
+        self
+    }
+
+    pub fn retry_options(mut self, retry_options: RetryOptions) -> Self{
+        self.retry_options = retry_options;
+        self
+    }
+
+    pub fn checkpoints(mut self, interval: u32, state_psw: &str) -> Self{
+        self.checkpoints = Some((interval, state_psw.to_string()));
+        self
+    }
+
+    pub fn max_payload_size(mut self, max_payload_size: usize) -> Self{
+        self.max_payload_size = max_payload_size;
+        self
+    }
+
+    pub fn schema(mut self, schema: PacketSchema) -> Self{
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn key_store(mut self, key_store: KeyStore) -> Self{
+        self.key_store = Some(key_store);
+        self
+    }
+
     pub fn build(self) -> ChannelWriter{
-        ChannelWriter::new(self.author_builder.build())
+        let mut writer = ChannelWriter::new(self.author_builder.build());
+        writer.set_single_depth(self.single_depth);
+        writer.set_retry_options(self.retry_options);
+        writer.set_max_payload_size(self.max_payload_size);
+        writer.set_schema(self.schema);
+        writer.set_key_store(self.key_store);
+        if let Some((interval, state_psw)) = self.checkpoints{
+            writer.set_checkpoints(interval, &state_psw);
+        }
+        writer
     }
 }
 
 
 pub struct ChannelReaderBuilder{
-    subscriber_builder: SubscriberBuilder
+    subscriber_builder: SubscriberBuilder,
+    auto_follow: bool,
+    schema: Option<PacketSchema>,
+    ratchet: Option<KeyRatchet>,
+    key_store: Option<KeyStore>
 }
 
 impl ChannelReaderBuilder{
 
     pub fn new() -> ChannelReaderBuilder{
         ChannelReaderBuilder{
-            subscriber_builder: SubscriberBuilder::new()
+            subscriber_builder: SubscriberBuilder::new(),
+            auto_follow: false,
+            schema: None,
+            ratchet: None,
+            key_store: None
         }
     }
 
@@ -64,7 +130,42 @@ impl ChannelReaderBuilder{
         self
     }
 
+    pub fn psk(mut self, psk_seed: &str) -> Self{
+        self.subscriber_builder = self.subscriber_builder.psk(psk_seed);
+        self
+    }
+
+    pub fn auto_follow(mut self, auto_follow: bool) -> Self{
+        self.auto_follow = auto_follow;
+        self
+    }
+
+    pub fn schema(mut self, schema: PacketSchema) -> Self{
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn ratchet(mut self, ratchet: KeyRatchet) -> Self{
+        self.ratchet = Some(ratchet);
+        self
+    }
+
+    pub fn key_store(mut self, key_store: KeyStore) -> Self{
+        self.key_store = Some(key_store);
+        self
+    }
+
     pub fn build(self, channel_id: &str, announce_id: &str) -> ChannelReader{
-        ChannelReader::new(self.subscriber_builder.build(), channel_id, announce_id)
+        let psks = self.subscriber_builder.psks().to_vec();
+        let mut reader = ChannelReader::new(self.subscriber_builder.build(), channel_id, announce_id);
+        // The subscriber already stores the keys, they are recorded so that they are exported with the state
+        for psk_seed in psks.iter(){
+            reader.add_psk(psk_seed);
+        }
+        reader.set_auto_follow(self.auto_follow);
+        reader.set_schema(self.schema);
+        reader.set_ratchet(self.ratchet);
+        reader.set_key_store(self.key_store);
+        reader
     }
 }
@@ -1,14 +1,22 @@
+use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::io::{Read, Write};
 
 use aead::generic_array::GenericArray;
 use anyhow::Result;
-use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use base64::{decode_config, URL_SAFE_NO_PAD};
+use bincode::Options;
 use chacha20poly1305::aead::{Aead, NewAead};
 use chacha20poly1305::XChaCha20Poly1305;
 use serde::{Deserialize, Serialize};
 
 use crate::utility::iota_utility::hash_string;
+use crate::channels::state_store::StateStore;
+use crate::channels::state_container::{is_state_container, open_state, seal_state, seal_state_with_key, KdfParams, StateChannelType};
+use crate::channels::outbox::{OutboxEntry, RetryOptions};
+use crate::payload::payload_schema::PacketSchema;
+use crate::payload::payload_ratchet::KeyRatchet;
+use crate::payload::payload_chunks::DEFAULT_MAX_PAYLOAD_SIZE;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ChannelState{
@@ -16,6 +24,22 @@ pub struct ChannelState{
     channel_id: String,
     announcement_id: String,
     last_msg_id: String,
+    branches: HashMap<String, String>,
+    single_depth: bool,
+    subscribers: Vec<String>,
+    psks: HashMap<String, String>,
+    outbox: Vec<OutboxEntry>,
+    closed: bool,
+    schema: Option<PacketSchema>,
+    ratchet: Option<KeyRatchet>,
+    channel_type: StateChannelType,
+    retry_options: RetryOptions,
+    checkpoint_interval: u32,
+    msgs_since_checkpoint: u32,
+    successor: Option<(String, String)>,
+    max_payload_size: u64,
+    has_access: bool,
+    revoked: bool,
 }
 
 impl ChannelState {
@@ -25,9 +49,97 @@ impl ChannelState {
             channel_id: channel_id.to_string(),
             announcement_id: announcement_id.to_string(),
             last_msg_id: last_public_msg.to_string(),
+            branches: HashMap::new(),
+            single_depth: false,
+            subscribers: vec![],
+            psks: HashMap::new(),
+            outbox: vec![],
+            closed: false,
+            schema: None,
+            ratchet: None,
+            channel_type: StateChannelType::Unknown,
+            retry_options: RetryOptions::default(),
+            checkpoint_interval: 0,
+            msgs_since_checkpoint: 0,
+            successor: None,
+            max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE as u64,
+            has_access: false,
+            revoked: false,
         }
     }
 
+    pub fn with_branches(mut self, branches: &HashMap<String, String>) -> ChannelState{
+        self.branches = branches.clone();
+        self
+    }
+
+    pub fn with_single_depth(mut self, single_depth: bool) -> ChannelState{
+        self.single_depth = single_depth;
+        self
+    }
+
+    pub fn with_subscribers(mut self, subscribers: &[String]) -> ChannelState{
+        self.subscribers = subscribers.to_vec();
+        self
+    }
+
+    pub fn with_psks(mut self, psks: &HashMap<String, String>) -> ChannelState{
+        self.psks = psks.clone();
+        self
+    }
+
+    pub fn with_outbox(mut self, outbox: &[OutboxEntry]) -> ChannelState{
+        self.outbox = outbox.to_vec();
+        self
+    }
+
+    pub fn with_closed(mut self, closed: bool) -> ChannelState{
+        self.closed = closed;
+        self
+    }
+
+    pub fn with_schema(mut self, schema: Option<PacketSchema>) -> ChannelState{
+        self.schema = schema;
+        self
+    }
+
+    pub fn with_ratchet(mut self, ratchet: Option<KeyRatchet>) -> ChannelState{
+        self.ratchet = ratchet;
+        self
+    }
+
+    pub fn with_channel_type(mut self, channel_type: StateChannelType) -> ChannelState{
+        self.channel_type = channel_type;
+        self
+    }
+
+    pub fn with_retry_options(mut self, retry_options: &RetryOptions) -> ChannelState{
+        self.retry_options = retry_options.clone();
+        self
+    }
+
+    pub fn with_checkpoints(mut self, checkpoint_interval: u32, msgs_since_checkpoint: u32) -> ChannelState{
+        self.checkpoint_interval = checkpoint_interval;
+        self.msgs_since_checkpoint = msgs_since_checkpoint;
+        self
+    }
+
+    pub fn with_successor(mut self, successor: &Option<(String, String)>) -> ChannelState{
+        self.successor = successor.clone();
+        self
+    }
+
+    pub fn with_max_payload_size(mut self, max_payload_size: usize) -> ChannelState{
+        self.max_payload_size = max_payload_size as u64;
+        self
+    }
+
+    pub fn with_access(mut self, has_access: bool, revoked: bool) -> ChannelState{
+        self.has_access = has_access;
+        self.revoked = revoked;
+        self
+    }
+
     pub fn from_file(file_path: &str, psw: &str) -> Result<ChannelState>{
         let mut fr = OpenOptions::new().read(true).open(file_path)?;
         let mut input = vec![];
@@ -37,6 +149,20 @@ impl ChannelState {
 }
 
 impl ChannelState{
+    ///
+    /// Load the state with the specified key from the store
+    ///
+    pub fn from_store(store: &dyn StateStore, key: &str, psw: &str) -> Result<ChannelState>{
+        match store.load(key)?{
+            None => Err(anyhow::Error::msg(format!("There is no state {} in the store", key))),
+            Some(state) => ChannelState::decrypt(&state, psw)
+        }
+    }
+
+    pub fn write_to_store(&self, store: &mut dyn StateStore, key: &str, psw: &str) -> Result<()>{
+        store.save(key, &self.encrypt(psw)?)
+    }
+
     pub fn write_to_file(&self, file_path: &str, psw: &str) -> Result<()>{
         let mut fr = OpenOptions::new()
             .write(true)
@@ -60,43 +186,125 @@ impl ChannelState{
     pub fn last_msg_id(&self) -> String {
         self.last_msg_id.clone()
     }
+    pub fn branches(&self) -> HashMap<String, String> {
+        self.branches.clone()
+    }
+    pub fn single_depth(&self) -> bool {
+        self.single_depth
+    }
+    pub fn subscribers(&self) -> Vec<String> {
+        self.subscribers.clone()
+    }
+    pub fn psks(&self) -> HashMap<String, String> {
+        self.psks.clone()
+    }
+    pub fn outbox(&self) -> Vec<OutboxEntry> {
+        self.outbox.clone()
+    }
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+    pub fn schema(&self) -> Option<PacketSchema> {
+        self.schema.clone()
+    }
+    pub fn ratchet(&self) -> Option<KeyRatchet> {
+        self.ratchet.clone()
+    }
+    pub fn channel_type(&self) -> StateChannelType {
+        self.channel_type
+    }
+    pub fn retry_options(&self) -> RetryOptions {
+        self.retry_options.clone()
+    }
+    pub fn checkpoint_interval(&self) -> u32 {
+        self.checkpoint_interval
+    }
+    pub fn msgs_since_checkpoint(&self) -> u32 {
+        self.msgs_since_checkpoint
+    }
+    pub fn successor(&self) -> Option<(String, String)> {
+        self.successor.clone()
+    }
+    pub fn max_payload_size(&self) -> usize {
+        self.max_payload_size as usize
+    }
+    pub fn has_access(&self) -> bool {
+        self.has_access
+    }
+    pub fn revoked(&self) -> bool {
+        self.revoked
+    }
 }
 
 impl ChannelState{
+    ///
+    /// Encrypt the state in a versioned container, with a key derived from the password by scrypt with a random salt
+    ///
     pub fn encrypt(&self, psw: &str) -> Result<Vec<u8>>{
         let bytes = bincode::serialize(&self)?;
+        seal_state(&bytes, psw, self.channel_type)
+    }
 
-        let (key, nonce) = get_key_nonce(psw);
-        let key = GenericArray::from_slice(&key[..]);
-        let nonce = GenericArray::from_slice(&nonce[..]);
-
-        let chacha = XChaCha20Poly1305::new(key);
-        let enc = match chacha.encrypt(nonce, bytes.as_ref()){
-            Ok(res) => res,
-            Err(_) => return Err(anyhow::Error::msg("Error during state encryption")),
-        };
-        let base64 = encode_config(&enc, URL_SAFE_NO_PAD);
-        Ok(base64.as_bytes().to_vec())
+    ///
+    /// Encrypt the state in a versioned container, with a key already derived from the password with the specified parameters
+    ///
+    pub fn encrypt_with_key(&self, kdf: &KdfParams, key: &[u8; 32]) -> Result<Vec<u8>>{
+        let bytes = bincode::serialize(&self)?;
+        seal_state_with_key(&bytes, kdf, key, self.channel_type)
     }
 
+    ///
+    /// Decrypt a state exported by encrypt. The legacy base64 exports are still accepted.
+    /// The whole input must be consumed, so a state is never decoded with the wrong layout
+    ///
     pub fn decrypt(input: &[u8], psw: &str) -> Result<ChannelState>{
+        let options = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .reject_trailing_bytes();
+        if is_state_container(input){
+            let (_, dec) = open_state(input, psw)?;
+            return Ok(options.deserialize::<ChannelState>(&dec)?);
+        }
+
         let bytes = decode_config(input, URL_SAFE_NO_PAD)?;
+        let legacy: LegacyChannelState = options.deserialize(&decrypt_legacy(&bytes, psw)?)?;
+        Ok(ChannelState::new(&legacy.user_state, &legacy.channel_id, &legacy.announcement_id, &legacy.last_msg_id))
+    }
 
-        let (key, nonce) = get_key_nonce(psw);
-        let key = GenericArray::from_slice(&key[..]);
-        let nonce = GenericArray::from_slice(&nonce[..]);
+    ///
+    /// Convert a legacy export in the current container format
+    ///
+    pub fn migrate(input: &[u8], psw: &str) -> Result<Vec<u8>>{
+        ChannelState::decrypt(input, psw)?.encrypt(psw)
+    }
+}
+
+///
+/// Layout of the states exported before the channel state was extended
+///
+#[derive(Deserialize)]
+struct LegacyChannelState{
+    user_state: Vec<u8>,
+    channel_id: String,
+    announcement_id: String,
+    last_msg_id: String,
+}
 
-        let chacha = XChaCha20Poly1305::new(key);
-        let dec = match chacha.decrypt(nonce, bytes.as_ref()){
-            Ok(res) => res,
-            Err(_) => return Err(anyhow::Error::msg("Error during state decryption")),
-        };
+fn decrypt_legacy(bytes: &[u8], psw: &str) -> Result<Vec<u8>>{
+    let (key, nonce) = get_key_nonce(psw);
+    let key = GenericArray::from_slice(&key[..]);
+    let nonce = GenericArray::from_slice(&nonce[..]);
 
-        let ch_state: ChannelState = bincode::deserialize(&dec)?;
-        Ok(ch_state)
+    let chacha = XChaCha20Poly1305::new(key);
+    match chacha.decrypt(nonce, bytes.as_ref()){
+        Ok(res) => Ok(res),
+        Err(_) => Err(anyhow::Error::msg("Error during state decryption")),
     }
 }
 
+///
+/// Legacy derivation of the key and the nonce, used only to decrypt the old states
+///
 fn get_key_nonce(psw: &str) -> (Vec<u8>, Vec<u8>) {
     let key_hash = &hash_string(psw)[..32];
     let nonce_hash = &hash_string(key_hash)[..24];
@@ -5,4 +5,7 @@ mod tangle_channel_reader;
 pub use tangle_channel_reader::ChannelReader;
 
 pub mod channel_state;
+pub mod state_container;
+pub mod state_store;
+pub mod outbox;
 mod builders;
@@ -0,0 +1,77 @@
+use std::cmp::min;
+
+use serde::{Deserialize, Serialize};
+
+///
+/// Packet waiting in the outbox of a ChannelWriter, with the payloads already encoded by the StreamsPacket
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OutboxEntry{
+    public: Vec<u8>,
+    masked: Vec<u8>,
+    branch: Option<String>,
+    key_id: Option<String>,
+}
+
+impl OutboxEntry{
+    pub fn new(public: Vec<u8>, masked: Vec<u8>, branch: Option<String>) -> OutboxEntry{
+        OutboxEntry{
+            public,
+            masked,
+            branch,
+            key_id: None
+        }
+    }
+
+    ///
+    /// Set the id of the key of the KeyStore used to encrypt the masked payload, so it can be linked to the msg once sent
+    ///
+    pub fn with_key_id(mut self, key_id: Option<String>) -> OutboxEntry{
+        self.key_id = key_id;
+        self
+    }
+
+    pub fn public(&self) -> &[u8] {
+        &self.public
+    }
+    pub fn masked(&self) -> &[u8] {
+        &self.masked
+    }
+    pub fn branch(&self) -> Option<&str> {
+        self.branch.as_deref()
+    }
+    pub fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+}
+
+///
+/// Retry policy used when flushing the outbox. The delay doubles at each attempt up to max_delay_ms.
+/// With queue_on_failure the packets that can't be sent are put in the outbox instead of being dropped.
+/// The queueing is disabled by default, so a failed send is returned as an error and nothing is queued
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RetryOptions{
+    pub max_retries: u32,
+    pub initial_delay_ms: u32,
+    pub max_delay_ms: u32,
+    pub queue_on_failure: bool,
+}
+
+impl Default for RetryOptions{
+    fn default() -> Self {
+        RetryOptions{
+            max_retries: 5,
+            initial_delay_ms: 500,
+            max_delay_ms: 30_000,
+            queue_on_failure: false
+        }
+    }
+}
+
+impl RetryOptions{
+    pub fn delay(&self, attempt: u32) -> u32{
+        let factor = 2u32.saturating_pow(attempt);
+        min(self.initial_delay_ms.saturating_mul(factor), self.max_delay_ms)
+    }
+}
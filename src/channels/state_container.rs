@@ -0,0 +1,252 @@
+use aead::Payload;
+use aead::generic_array::GenericArray;
+use anyhow::Result;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::XChaCha20Poly1305;
+use crypto::hashes::{
+    Digest,
+    blake2b::Blake2b256
+};
+use rand::Rng;
+use scrypt::{scrypt, Params};
+use serde::{Deserialize, Serialize};
+
+use crate::utility::iota_utility::current_timestamp;
+
+///
+/// First bytes of an exported state. The first one is not valid in base64, so containers
+/// can't be mistaken for the legacy base64 exports
+///
+pub const STATE_MAGIC: [u8; 4] = [0x89, b'S', b'T', b'C'];
+
+///
+/// Current version of the container format
+///
+pub const STATE_FORMAT_VERSION: u16 = 1;
+
+pub const CIPHER_XCHACHA20_POLY1305: u8 = 1;
+pub const KDF_SCRYPT: u8 = 1;
+
+const CHECKSUM_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+///
+/// Bounds of the key derivation parameters read from a container, so a crafted header can't make the import
+/// exhaust the memory or the cpu. scrypt needs about 128 * r * 2^log_n bytes, 32 MiB with the defaults
+///
+pub const MAX_KDF_MEMORY: u64 = 64 * 1024 * 1024;
+pub const MAX_P: u32 = 1;
+pub const SALT_LEN: usize = 16;
+
+///
+/// Type of the channel the state belongs to
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum StateChannelType{
+    Unknown,
+    SingleBranch,
+    MultiBranch,
+    SingleDepth,
+}
+
+///
+/// Parameters of the derivation of the encryption key from the password
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KdfParams{
+    pub algorithm: u8,
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+    pub salt: Vec<u8>,
+}
+
+impl Default for KdfParams{
+    fn default() -> Self {
+        KdfParams{
+            algorithm: KDF_SCRYPT,
+            log_n: 15,
+            r: 8,
+            p: 1,
+            salt: vec![]
+        }
+    }
+}
+
+impl KdfParams{
+    pub fn with_random_salt() -> KdfParams{
+        let mut salt = vec![0u8; SALT_LEN];
+        rand::thread_rng().fill(&mut salt[..]);
+        KdfParams{ salt, ..KdfParams::default() }
+    }
+
+    pub fn derive_key(&self, psw: &str) -> Result<[u8; 32]>{
+        if self.algorithm != KDF_SCRYPT{
+            return Err(anyhow::Error::msg(format!("Unsupported key derivation function {}", self.algorithm)));
+        }
+        let memory = 1u64.checked_shl(self.log_n as u32)
+            .and_then(|n| n.checked_mul(128 * self.r as u64));
+        match memory{
+            Some(memory) if memory <= MAX_KDF_MEMORY && self.p <= MAX_P => {},
+            _ => return Err(anyhow::Error::msg("The key derivation parameters exceed the allowed bounds"))
+        }
+        if self.salt.len() != SALT_LEN{
+            return Err(anyhow::Error::msg(format!("The key derivation salt must be {} bytes long", SALT_LEN)));
+        }
+        let params = match Params::new(self.log_n, self.r, self.p){
+            Ok(params) => params,
+            Err(_) => return Err(anyhow::Error::msg("Invalid key derivation parameters")),
+        };
+        let mut key = [0u8; 32];
+        if scrypt(psw.as_bytes(), &self.salt, &params, &mut key).is_err(){
+            return Err(anyhow::Error::msg("Error during key derivation"));
+        }
+        Ok(key)
+    }
+}
+
+///
+/// Plain header of an exported state. It is authenticated together with the encrypted state
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StateHeader{
+    version: u16,
+    kdf: KdfParams,
+    cipher: u8,
+    created_at: u64,
+    channel_type: StateChannelType,
+}
+
+impl StateHeader{
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+    pub fn kdf(&self) -> KdfParams {
+        self.kdf.clone()
+    }
+    pub fn cipher(&self) -> u8 {
+        self.cipher
+    }
+    pub fn created_at(&self) -> u64 {
+        self.created_at
+    }
+    pub fn channel_type(&self) -> StateChannelType {
+        self.channel_type
+    }
+}
+
+///
+/// Check if the bytes start like a state container
+///
+pub fn is_state_container(bytes: &[u8]) -> bool{
+    bytes.starts_with(&STATE_MAGIC)
+}
+
+///
+/// Encrypt the serialized state in a container.
+/// The layout is magic | version (u16 BE) | header length (u32 BE) | header | nonce | ciphertext | Blake2b checksum
+///
+pub fn seal_state(state: &[u8], psw: &str, channel_type: StateChannelType) -> Result<Vec<u8>>{
+    let kdf = KdfParams::with_random_salt();
+    let key = kdf.derive_key(psw)?;
+    seal_state_with_key(state, &kdf, &key, channel_type)
+}
+
+///
+/// Encrypt the serialized state in a container with a key already derived with the specified parameters.
+/// It lets the states saved often to the same target skip the key derivation
+///
+pub fn seal_state_with_key(state: &[u8], kdf: &KdfParams, key: &[u8; 32], channel_type: StateChannelType) -> Result<Vec<u8>>{
+    let header = StateHeader{
+        version: STATE_FORMAT_VERSION,
+        kdf: kdf.clone(),
+        cipher: CIPHER_XCHACHA20_POLY1305,
+        created_at: current_timestamp(),
+        channel_type
+    };
+    let header_bytes = bincode::serialize(&header)?;
+    let prefix = [
+        &STATE_MAGIC[..],
+        &STATE_FORMAT_VERSION.to_be_bytes(),
+        &(header_bytes.len() as u32).to_be_bytes(),
+        &header_bytes
+    ].concat();
+
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill(&mut nonce[..]);
+    let chacha = XChaCha20Poly1305::new(GenericArray::from_slice(key));
+    let enc = match chacha.encrypt(GenericArray::from_slice(&nonce), Payload{ msg: state, aad: &prefix }){
+        Ok(res) => res,
+        Err(_) => return Err(anyhow::Error::msg("Error during state encryption")),
+    };
+
+    let body = [&prefix[..], &nonce, &enc].concat();
+    let checksum = Blake2b256::digest(&body);
+    Ok([&body[..], &checksum].concat())
+}
+
+///
+/// Read the header of a container without decrypting it
+///
+pub fn read_state_header(bytes: &[u8]) -> Result<StateHeader>{
+    Ok(split_container(bytes)?.0)
+}
+
+///
+/// Decrypt the serialized state of a container
+///
+/// # Return Value
+/// It returns a Tuple containing (header, state_bytes). A corrupt container and a wrong password
+/// are reported with different errors
+///
+pub fn open_state(bytes: &[u8], psw: &str) -> Result<(StateHeader, Vec<u8>)>{
+    let (header, prefix_len) = split_container(bytes)?;
+    if header.cipher != CIPHER_XCHACHA20_POLY1305{
+        return Err(anyhow::Error::msg(format!("Unsupported state cipher {}", header.cipher)));
+    }
+
+    let body = &bytes[..bytes.len() - CHECKSUM_LEN];
+    if body.len() < prefix_len + NONCE_LEN{
+        return Err(anyhow::Error::msg("The state is corrupt"));
+    }
+    let (prefix, rest) = body.split_at(prefix_len);
+    let (nonce, enc) = rest.split_at(NONCE_LEN);
+
+    let key = header.kdf.derive_key(psw)?;
+    let chacha = XChaCha20Poly1305::new(GenericArray::from_slice(&key));
+    match chacha.decrypt(GenericArray::from_slice(nonce), Payload{ msg: enc, aad: prefix }){
+        Ok(state) => Ok((header, state)),
+        Err(_) => Err(anyhow::Error::msg("Wrong password for the state")),
+    }
+}
+
+///
+/// Verify the checksum and the version of a container and decode its header
+///
+/// # Return Value
+/// It returns a Tuple containing (header, length of magic, version and header)
+///
+fn split_container(bytes: &[u8]) -> Result<(StateHeader, usize)>{
+    if !is_state_container(bytes){
+        return Err(anyhow::Error::msg("The data is not a channel state"));
+    }
+    if bytes.len() < STATE_MAGIC.len() + 6 + CHECKSUM_LEN{
+        return Err(anyhow::Error::msg("The state is corrupt"));
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if Blake2b256::digest(body).as_slice() != checksum{
+        return Err(anyhow::Error::msg("The state is corrupt"));
+    }
+
+    let version = u16::from_be_bytes([body[4], body[5]]);
+    if version == 0 || version > STATE_FORMAT_VERSION{
+        return Err(anyhow::Error::msg(format!("Unsupported state version {}", version)));
+    }
+    let header_len = u32::from_be_bytes([body[6], body[7], body[8], body[9]]) as usize;
+    let prefix_len = match 10usize.checked_add(header_len){
+        Some(prefix_len) if prefix_len <= body.len() => prefix_len,
+        _ => return Err(anyhow::Error::msg("The state is corrupt"))
+    };
+    let header: StateHeader = bincode::deserialize(&body[10..prefix_len])?;
+    Ok((header, prefix_len))
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::channels::channel_state::ChannelState;
+use crate::channels::state_container::KdfParams;
+
+///
+/// Storage of the exported channel states, identified by key
+///
+pub trait StateStore{
+    ///
+    /// Load the state with the specified key. It returns None if there is no such state
+    ///
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    fn save(&mut self, key: &str, state: &[u8]) -> Result<()>;
+    fn delete(&mut self, key: &str) -> Result<()>;
+    fn list(&self) -> Result<Vec<String>>;
+
+    ///
+    /// Get the error of the last completed save or delete, for the stores that complete them in background
+    ///
+    fn last_error(&self) -> Option<String>{
+        None
+    }
+}
+
+///
+/// Stores each state in a file of a directory, named after its key
+///
+pub struct FileStateStore{
+    dir: PathBuf,
+}
+
+impl FileStateStore{
+    pub fn new(dir: &str) -> Result<FileStateStore>{
+        std::fs::create_dir_all(dir)?;
+        Ok(FileStateStore{ dir: PathBuf::from(dir) })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf>{
+        if key.is_empty() || key.contains(|c| c == '/' || c == '\\') || key.starts_with('.'){
+            return Err(anyhow::Error::msg(format!("Invalid state key {}", key)));
+        }
+        Ok(self.dir.join(format!("{}.state", key)))
+    }
+}
+
+impl StateStore for FileStateStore{
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.path(key)?;
+        if !path.exists(){
+            return Ok(None);
+        }
+        let mut fr = OpenOptions::new().read(true).open(path)?;
+        let mut state = vec![];
+        fr.read_to_end(&mut state)?;
+        Ok(Some(state))
+    }
+
+    fn save(&mut self, key: &str, state: &[u8]) -> Result<()> {
+        let mut fr = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(self.path(key)?)?;
+        fr.write_all(state)?;
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<()> {
+        let path = self.path(key)?;
+        if path.exists(){
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let mut keys = vec![];
+        for entry in std::fs::read_dir(&self.dir)?{
+            let path = entry?.path();
+            if path.extension().map(|ext| ext == "state").unwrap_or(false){
+                if let Some(key) = path.file_stem(){
+                    keys.push(key.to_string_lossy().to_string());
+                }
+            }
+        }
+        Ok(keys)
+    }
+}
+
+///
+/// Keeps the states in memory. They are lost when the store is dropped
+///
+#[derive(Default)]
+pub struct MemoryStateStore{
+    states: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryStateStore{
+    pub fn new() -> MemoryStateStore{
+        MemoryStateStore::default()
+    }
+}
+
+impl StateStore for MemoryStateStore{
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.states.get(key).cloned())
+    }
+
+    fn save(&mut self, key: &str, state: &[u8]) -> Result<()> {
+        self.states.insert(key.to_string(), state.to_vec());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<()> {
+        self.states.remove(key);
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self.states.keys().cloned().collect())
+    }
+}
+
+///
+/// Store, key and password used to save the state automatically. The encryption key is derived
+/// at the first save and reused for the next ones, and the outcome of the last save is kept
+///
+pub(crate) struct Autosave{
+    store: Box<dyn StateStore>,
+    key: String,
+    psw: String,
+    derived_key: Option<(KdfParams, [u8; 32])>,
+    last_error: Option<String>,
+}
+
+impl Autosave{
+    pub(crate) fn new(store: Box<dyn StateStore>, key: &str, psw: &str) -> Autosave{
+        Autosave{
+            store,
+            key: key.to_string(),
+            psw: psw.to_string(),
+            derived_key: None,
+            last_error: None
+        }
+    }
+
+    pub(crate) fn psw(&self) -> &str{
+        &self.psw
+    }
+
+    ///
+    /// Get the error of the last save. The saves completed in background by the store report their own error
+    ///
+    pub(crate) fn last_error(&self) -> Option<String>{
+        self.last_error.clone().or_else(|| self.store.last_error())
+    }
+
+    ///
+    /// Encrypt and save the exported state, recording the error if the export or the save failed
+    ///
+    pub(crate) fn save(&mut self, state: Result<ChannelState>) -> Result<()>{
+        let res = state.and_then(|state| self.save_state(&state));
+        self.last_error = res.as_ref().err().map(|e| e.to_string());
+        res
+    }
+
+    fn save_state(&mut self, state: &ChannelState) -> Result<()>{
+        let (kdf, key) = match &self.derived_key{
+            Some(derived_key) => derived_key.clone(),
+            None => {
+                let kdf = KdfParams::with_random_salt();
+                let key = kdf.derive_key(&self.psw)?;
+                self.derived_key = Some((kdf.clone(), key));
+                (kdf, key)
+            }
+        };
+        let bytes = state.encrypt_with_key(&kdf, &key)?;
+        self.store.save(&self.key, &bytes)
+    }
+}
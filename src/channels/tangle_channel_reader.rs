@@ -6,15 +6,25 @@ use iota_streams::{
     app_channels::api::tangle::Subscriber
 };
 use iota_streams::app::message::HasLink;
-use iota_streams::app_channels::api::tangle::MessageContent;
+use iota_streams::app_channels::api::tangle::Address;
+use iota_streams::app_channels::api::tangle::{MessageContent, PublicKey};
 
-use crate::utility::iota_utility::{create_link, msg_index, hash_string};
-use crate::payload::payload_serializers::RawPacket;
+use crate::utility::iota_utility::{create_link, msg_index, hash_string, untag_branch_payload, public_key_to_hex, create_psk, pskid_to_hex};
+use crate::payload::payload_serializers::{RawPacket, JsonPacket, JsonSerializer};
+use crate::payload::payload_schema::PacketSchema;
+use crate::payload::payload_ratchet::KeyRatchet;
+use crate::payload::payload_keystore::KeyStore;
+use crate::channels::state_container::StateChannelType;
+use crate::channels::state_store::{Autosave, StateStore};
+use crate::payload::payload_types::{StreamsPacket, StreamsPacketSerializer};
+use crate::payload::payload_chunks::{decode_header, ChunkAssembler, ChunkHeader};
+use crate::payload::payload_files::{FileAssembler, FilePart, ReceivedFile};
 use crate::channels::channel_state::ChannelState;
 use iota_streams::app::transport::tangle::client::SendOptions;
 use crate::user_builders::subscriber_builder::SubscriberBuilder;
 use crate::channels::builders::channel_builders::ChannelReaderBuilder;
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
+use serde_json::Value;
 
 ///
 /// Channel Reader
@@ -23,7 +33,21 @@ pub struct ChannelReader {
     subscriber: Subscriber<StreamsClient>,
     channel_address: String,
     announcement_id: String,
-    unread_msgs: VecDeque<(String, Vec<u8>, Vec<u8>)>,
+    unread_msgs: VecDeque<Result<(String, Vec<u8>, Vec<u8>)>>,
+    assemblers: HashMap<String, ChunkAssembler>,
+    files: FileAssembler,
+    msg_branches: HashMap<String, String>,
+    psks: HashMap<String, String>,
+    has_access: bool,
+    revoked: bool,
+    closed: bool,
+    successor: Option<(String, String)>,
+    auto_follow: bool,
+    schema: Option<PacketSchema>,
+    non_conforming: HashSet<String>,
+    ratchet: Option<KeyRatchet>,
+    key_store: Option<KeyStore>,
+    autosave: Option<Autosave>,
 }
 
 impl ChannelReader {
@@ -43,7 +67,21 @@ impl ChannelReader {
             subscriber,
             channel_address: channel_address.to_string(),
             announcement_id: announcement_id.to_string(),
-            unread_msgs: VecDeque::new()
+            unread_msgs: VecDeque::new(),
+            assemblers: HashMap::new(),
+            files: FileAssembler::new(),
+            msg_branches: HashMap::new(),
+            psks: HashMap::new(),
+            has_access: false,
+            revoked: false,
+            closed: false,
+            successor: None,
+            auto_follow: false,
+            schema: None,
+            non_conforming: HashSet::new(),
+            ratchet: None,
+            key_store: None,
+            autosave: None,
         }
     }
 
@@ -56,6 +94,40 @@ impl ChannelReader {
         Ok(channel)
     }
 
+    ///
+    /// Restore the channels from the state saved in the store with the specified key
+    ///
+    pub fn import_from_store(store: &dyn StateStore, key: &str, psw: &str, node_url: Option<&str>, send_options: Option<SendOptions>) -> Result<ChannelReader>{
+        let channel_state = ChannelState::from_store(store, key, psw)?;
+        ChannelReader::import(&channel_state, psw, node_url, send_options)
+    }
+
+    ///
+    /// Stores the channels state in the store with the specified key
+    ///
+    pub fn export_to_store(&self, store: &mut dyn StateStore, key: &str, psw: &str) -> Result<()>{
+        let channel_state = self.export(psw)?;
+        channel_state.write_to_store(store, key, psw)
+    }
+
+    ///
+    /// Save the state in the store after each fetch
+    ///
+    pub fn set_autosave(&mut self, store: Box<dyn StateStore>, key: &str, psw: &str){
+        self.autosave = Some(Autosave::new(store, key, psw));
+    }
+
+    pub fn disable_autosave(&mut self){
+        self.autosave = None;
+    }
+
+    ///
+    /// Get the error of the last autosave, if it failed. It is cleared by the next successful save
+    ///
+    pub fn last_autosave_error(&self) -> Option<String>{
+        self.autosave.as_ref().and_then(|autosave| autosave.last_error())
+    }
+
     ///
     /// Export the channels state into an encrypted byte array.
     ///
@@ -71,26 +143,39 @@ impl ChannelReader {
         let link = create_link(&self.channel_address, &self.announcement_id)?;
         self.subscriber.receive_announcement(&link).await?;
 
-        if !self.fetch_all_msgs().await{
-            return Ok(());
-        }
+        self.fetch_all_msgs().await;
+        Ok(())
+    }
 
-        let comp = format!("{}:{}.state", self.channel_address, self.announcement_id);
-        let msg = &self.unread_msgs[0];
-        let packet = match RawPacket::from_streams_response(&msg.1, &msg.2, &None){
-            Ok(packet) => packet,
-            Err(_) => return Ok(())
-        };
+    ///
+    /// Send a subscription request to the channel author. The reader must be attached
+    ///
+    /// # Return Value
+    /// It returns the id of the subscription msg, that must be sent to the author along with the public key
+    ///
+    pub async fn subscribe(&mut self) -> Result<String> {
+        let link = create_link(&self.channel_address, &self.announcement_id)?;
+        let sub_link = self.subscriber.send_subscribe(&link).await?;
+        Ok(sub_link.msgid.to_string())
+    }
 
-        match packet.deserialize_public::<String>(){
-            Ok(state_msg) => {
-                if state_msg == comp{
-                    self.unread_msgs.remove(0);
-                }
-            }
-            Err(_) => {}
-        };
-        Ok(())
+    ///
+    /// Get the hex encoded public key of the reader
+    ///
+    pub fn public_key(&self) -> String{
+        public_key_to_hex(self.subscriber.get_pk())
+    }
+
+    ///
+    /// Store a pre shared key derived from the specified seed, to read the msgs of the keyloads that include it.
+    /// It returns the hex encoded id of the key
+    ///
+    pub fn add_psk(&mut self, psk_seed: &str) -> String{
+        let (pskid, psk) = create_psk(psk_seed);
+        let pskid_hex = pskid_to_hex(&pskid);
+        self.subscriber.store_psk(pskid, psk);
+        self.psks.insert(pskid_hex.clone(), psk_seed.to_string());
+        pskid_hex
     }
 
     ///
@@ -101,15 +186,245 @@ impl ChannelReader {
     ///
     pub async fn fetch_raw_msgs(&mut self) -> u32 {
         self.fetch_all_msgs().await;
+        while self.auto_follow && self.successor.is_some(){
+            if self.follow_successor().await.is_err(){
+                break;
+            }
+        }
         self.unread_msgs.len() as u32
     }
 
+    ///
+    /// Check if the author has closed the channel. No msg is fetched after the end of stream msg
+    ///
+    pub fn is_closed(&self) -> bool{
+        self.closed
+    }
+
+    ///
+    /// Get the (channel_id, announce_id) of the channel that continues the closed one, if any
+    ///
+    pub fn successor(&self) -> Option<(String, String)>{
+        self.successor.clone()
+    }
+
+    ///
+    /// Follow the successor of a closed channel, attaching the reader to it.
+    /// The msgs of the closed channel that have not been popped yet are kept
+    ///
+    /// # Return Value
+    /// It returns false if there is no successor to follow
+    ///
+    pub async fn follow_successor(&mut self) -> Result<bool> {
+        let (channel_id, announce_id) = match self.successor.take(){
+            None => return Ok(false),
+            Some(successor) => successor
+        };
+        self.subscriber.unregister();
+        self.channel_address = channel_id;
+        self.announcement_id = announce_id;
+        self.closed = false;
+        self.has_access = false;
+        self.revoked = false;
+        self.attach().await?;
+        Ok(true)
+    }
+
+    ///
+    /// Follow automatically the successor of a closed channel when fetching new msgs
+    ///
+    pub fn set_auto_follow(&mut self, auto_follow: bool){
+        self.auto_follow = auto_follow;
+    }
+
+    ///
+    /// Fetch directly the msg with the specified index of a single depth channel, without
+    /// walking the previous msgs. Index 0 is the first msg sent after the announcement.
+    /// If the msg is the manifest of a chunked packet, its chunks are fetched from the next indexes
+    ///
+    /// # Return Value
+    /// It returns a Tuple containing (msg_id, public_bytes, masked_bytes) or None if the msg has no payload.
+    /// Chunked packets are returned reassembled, with the id of their manifest msg
+    ///
+    pub async fn fetch_msg(&mut self, index: u32) -> Result<Option<(String, Vec<u8>, Vec<u8>)>> {
+        let (msg_id, p, m) = match self.fetch_payloads(index).await?{
+            None => return Ok(None),
+            Some(msg) => msg
+        };
+        let chunks = match decode_header(&p)?{
+            None => return Ok(Some((msg_id, p, m))),
+            Some((ChunkHeader::Manifest{ chunks, .. }, _)) => chunks,
+            Some((ChunkHeader::Chunk{ .. }, _)) => {
+                return Err(anyhow::Error::msg(format!("Msg {} is a chunk, fetch the manifest of the chunked msg", msg_id)));
+            }
+        };
+
+        // The chunks are sent right after their manifest, so they have the next indexes
+        let mut assembler = ChunkAssembler::new();
+        assembler.push(&msg_id, &p, &m);
+        for offset in 1..=chunks{
+            let chunk_index = match index.checked_add(offset){
+                None => break,
+                Some(chunk_index) => chunk_index
+            };
+            let (chunk_id, p, m) = match self.fetch_payloads(chunk_index).await?{
+                None => break,
+                Some(msg) => msg
+            };
+            if let Some(packet) = assembler.push(&chunk_id, &p, &m).pop(){
+                return packet.map(Some);
+            }
+        }
+        Err(anyhow::Error::msg(format!("Chunked msg {} is incomplete", msg_id)))
+    }
+
     pub fn has_next_msg(&self) -> bool{
         !self.unread_msgs.is_empty()
     }
 
-    pub fn pop_next_msg(&mut self) -> Option<(String, Vec<u8>, Vec<u8>)>{
-        self.unread_msgs.pop_front()
+    ///
+    /// Pop the next unread msg
+    ///
+    /// # Return Value
+    /// It returns a Tuple containing (msg_id, public_bytes, masked_bytes) or None if there are no unread msgs.
+    /// Chunked packets are returned once reassembled, with the id of their manifest msg, while incomplete or
+    /// corrupt chunk sets are returned as errors.
+    /// The msgs sent after a revocation are returned with an empty masked payload, since the reader is no
+    /// longer able to decrypt it. Once all the msgs are popped, it returns an error if the access is revoked
+    ///
+    pub fn pop_next_msg(&mut self) -> Result<Option<(String, Vec<u8>, Vec<u8>)>>{
+        match self.unread_msgs.pop_front(){
+            Some(msg) => msg.map(Some),
+            None if self.revoked => Err(anyhow::Error::msg("The access to the channel has been revoked")),
+            None => Ok(None)
+        }
+    }
+
+    ///
+    /// Take the file msgs out of the unread msgs and return the next file whose chunks have all been received.
+    /// The Blake2b hash of the content is verified before returning the file
+    ///
+    /// # Return Value
+    /// It returns None if no file is complete yet, or an error if the file is corrupt
+    ///
+    pub fn receive_file(&mut self, key_nonce: &Option<([u8;32], [u8;24])>) -> Result<Option<ReceivedFile>>{
+        let comp = format!("{}:{}.file", self.channel_address, self.announcement_id);
+        let mut remaining = VecDeque::new();
+        for msg in self.unread_msgs.drain(..){
+            if let Ok((_, p, m)) = &msg{
+                let part = RawPacket::from_streams_response(p, m, key_nonce)
+                    .and_then(|packet| packet.deserialize::<String, FilePart>());
+                match part{
+                    Ok((file_msg, part)) if file_msg == comp => {
+                        self.files.push(part);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            remaining.push_back(msg);
+        }
+        self.unread_msgs = remaining;
+
+        self.files.take_complete().transpose()
+    }
+
+    ///
+    /// Check if the access of the reader has been revoked by the author, i.e. the last keyload
+    /// does not include the reader after a previous one granted the access
+    ///
+    pub fn is_revoked(&self) -> bool{
+        self.revoked
+    }
+
+    ///
+    /// Set the store of the per msg keys. The masked payloads encrypted with its keys can be read
+    /// with StreamsPacket::from_streams_response_with_key_store, until the keys are erased
+    ///
+    pub fn set_key_store(&mut self, key_store: Option<KeyStore>){
+        self.key_store = key_store;
+    }
+
+    pub fn key_store(&self) -> Option<&KeyStore>{
+        self.key_store.as_ref()
+    }
+
+    ///
+    /// Set the ratchet exported by the writer. The masked payloads encrypted with a ratchet key can be read
+    /// with StreamsPacket::from_streams_response_with_ratchet, only from the ratchet position onward
+    ///
+    pub fn set_ratchet(&mut self, ratchet: Option<KeyRatchet>){
+        self.ratchet = ratchet;
+    }
+
+    pub fn ratchet(&self) -> Option<KeyRatchet>{
+        self.ratchet.clone()
+    }
+
+    ///
+    /// Decode the payloads of a msg, decrypting the masked data with the ratchet of the reader.
+    /// The ratchet moves past the position of the msg and the new position is autosaved
+    ///
+    pub fn decode_ratcheted_packet<P>(&mut self, public: &[u8], masked: &[u8]) -> Result<StreamsPacket<P>>
+    where
+        P: StreamsPacketSerializer
+    {
+        let packet = match self.ratchet.as_mut(){
+            None => return Err(anyhow::Error::msg("The reader has no ratchet")),
+            Some(ratchet) => StreamsPacket::from_streams_response_with_ratchet(public, masked, ratchet)?
+        };
+        let _ = self.autosave();
+        Ok(packet)
+    }
+
+    ///
+    /// Set the JSON Schema used to validate the json packets. The schema announced by the author replaces it
+    ///
+    pub fn set_schema(&mut self, schema: Option<PacketSchema>){
+        self.schema = schema;
+    }
+
+    pub fn schema(&self) -> Option<PacketSchema>{
+        self.schema.clone()
+    }
+
+    ///
+    /// Validate a json packet against the schema of the channel, flagging the msg if it does not conform.
+    /// The masked payload is validated only if it can be decrypted
+    ///
+    pub fn validate_msg(&mut self, msg_id: &str, public: &[u8], masked: &[u8], key_nonce: &Option<([u8;32], [u8;24])>) -> Result<()>{
+        let schema = match &self.schema{
+            None => return Ok(()),
+            Some(schema) => schema.clone()
+        };
+        let res = JsonPacket::from_streams_response(public, masked, key_nonce)
+            .and_then(|packet| schema.validate_packet(&packet));
+        if res.is_err(){
+            self.non_conforming.insert(msg_id.to_string());
+        }
+        res
+    }
+
+    ///
+    /// Check if the msg has not been flagged as non conforming to the schema of the channel.
+    /// The public payload of the json packets is validated when they are fetched
+    ///
+    pub fn is_conforming(&self, msg_id: &str) -> bool{
+        !self.non_conforming.contains(msg_id)
+    }
+
+    ///
+    /// Get the ids of the msgs that do not conform to the schema of the channel
+    ///
+    pub fn non_conforming_msgs(&self) -> Vec<String>{
+        self.non_conforming.iter().cloned().collect()
+    }
+
+    ///
+    /// Get the name of the branch the msg belongs to. It returns None for msgs sent outside of a named branch
+    ///
+    pub fn msg_branch(&self, msg_id: &str) -> Option<String>{
+        self.msg_branches.get(msg_id).cloned()
     }
 
     ///
@@ -131,7 +446,7 @@ impl ChannelReader {
 impl ChannelReader{
 
     fn import(channel_state: &ChannelState, psw: &str, node_url: Option<&str>, send_options: Option<SendOptions>) -> Result<ChannelReader>{
-        let subscriber = SubscriberBuilder::build_from_state(
+        let mut subscriber = SubscriberBuilder::build_from_state(
             &channel_state.user_state(),
             psw,
             node_url,
@@ -139,38 +454,233 @@ impl ChannelReader{
         )?;
         let channel_address = subscriber.channel_address().unwrap().to_string();
 
+        let psks = channel_state.psks();
+        for psk_seed in psks.values(){
+            let (pskid, psk) = create_psk(psk_seed);
+            subscriber.store_psk(pskid, psk);
+        }
+
         Ok(ChannelReader {
             subscriber,
             channel_address,
             announcement_id: channel_state.announcement_id(),
             unread_msgs: VecDeque::new(),
+            assemblers: HashMap::new(),
+            files: FileAssembler::new(),
+            msg_branches: HashMap::new(),
+            psks,
+            has_access: channel_state.has_access(),
+            revoked: channel_state.revoked(),
+            closed: channel_state.closed(),
+            successor: channel_state.successor(),
+            auto_follow: false,
+            schema: channel_state.schema(),
+            non_conforming: HashSet::new(),
+            ratchet: channel_state.ratchet(),
+            key_store: None,
+            autosave: None,
         })
     }
 
     fn export(&self, psw: &str) -> Result<ChannelState>{
         let psw_hash = hash_string(psw);
         let author_state = self.subscriber.export(&psw_hash)?;
-        Ok(ChannelState::new(&author_state, &self.channel_address, &self.announcement_id, ""))
+        Ok(ChannelState::new(&author_state, &self.channel_address, &self.announcement_id, "")
+            .with_psks(&self.psks)
+            .with_access(self.has_access, self.revoked)
+            .with_closed(self.closed)
+            .with_successor(&self.successor)
+            .with_schema(self.schema.clone())
+            .with_ratchet(self.ratchet.clone())
+            .with_channel_type(match self.subscriber.is_multi_branching(){
+                true => StateChannelType::MultiBranch,
+                false => StateChannelType::SingleBranch
+            }))
+    }
+
+    ///
+    /// Check if the msg is a state checkpoint published by the author
+    ///
+    fn is_state_msg(&self, public: &[u8], masked: &[u8]) -> bool{
+        let comp = format!("{}:{}.state", self.channel_address, self.announcement_id);
+        match RawPacket::from_streams_response(public, masked, &None)
+            .and_then(|packet| packet.deserialize_public::<String>()){
+            Ok(state_msg) => state_msg == comp,
+            Err(_) => false
+        }
+    }
+
+    ///
+    /// Check if the msg is the end of stream msg of the channel and store its successor.
+    /// Only the end of stream msgs signed by the author close the channel
+    ///
+    fn check_close(&mut self, public: &[u8], masked: &[u8], pk: &PublicKey) -> bool{
+        let comp = format!("{}:{}.close", self.channel_address, self.announcement_id);
+        let (close_msg, successor): (String, Option<(String, String)>) = match RawPacket::from_streams_response(public, masked, &None)
+            .and_then(|packet| packet.deserialize()){
+            Ok(res) => res,
+            Err(_) => return false
+        };
+        if close_msg != comp || !self.is_author(pk){
+            return false;
+        }
+
+        self.closed = true;
+        self.successor = successor;
+        true
+    }
+
+    ///
+    /// Check if the msg announces the schema of the channel and store it.
+    /// Only the schemas announced by the author replace the schema of the reader
+    ///
+    fn check_schema_msg(&mut self, public: &[u8], masked: &[u8], pk: &PublicKey) -> bool{
+        let comp = format!("{}:{}.schema", self.channel_address, self.announcement_id);
+        let (schema_msg, schema): (String, PacketSchema) = match RawPacket::from_streams_response(public, masked, &None)
+            .and_then(|packet| packet.deserialize()){
+            Ok(res) => res,
+            Err(_) => return false
+        };
+        if schema_msg != comp || !self.is_author(pk){
+            return false;
+        }
+
+        self.schema = Some(schema);
+        true
+    }
+
+    ///
+    /// Flag the json packets whose public payload does not conform to the schema. Only the packets whose envelope
+    /// declares the json serializer or a json content type are validated, because the payloads of the other
+    /// packets can be valid json by chance
+    ///
+    fn check_conformance(&mut self, msg_id: &str, public: &[u8]){
+        let schema = match &self.schema{
+            None => return,
+            Some(schema) => schema
+        };
+        let packet = match JsonPacket::from_streams_response(public, public, &None){
+            Ok(packet) => packet,
+            Err(_) => return
+        };
+        let is_json = match packet.envelope(){
+            None => false,
+            Some(envelope) => envelope.serializer_id() == JsonSerializer::SERIALIZER_ID || envelope.content_type().ends_with("json")
+        };
+        if !is_json{
+            return;
+        }
+        let data: Value = match packet.deserialize_public_auto(){
+            Ok(data) => data,
+            Err(_) => return
+        };
+        if schema.validate_public(&data).is_err(){
+            self.non_conforming.insert(msg_id.to_string());
+        }
+    }
+
+    fn is_author(&self, pk: &PublicKey) -> bool{
+        match self.subscriber.author_public_key(){
+            None => false,
+            Some(author_pk) => author_pk == pk
+        }
+    }
+
+    fn autosave(&mut self) -> Result<()>{
+        let state = match &self.autosave{
+            None => return Ok(()),
+            Some(autosave) => self.export(autosave.psw())
+        };
+        match self.autosave.as_mut(){
+            None => Ok(()),
+            Some(autosave) => autosave.save(state)
+        }
+    }
+
+    async fn fetch_payloads(&mut self, index: u32) -> Result<Option<(String, Vec<u8>, Vec<u8>)>> {
+        let anchor = create_link(&self.channel_address, &self.announcement_id)?;
+        let msg = self.subscriber.receive_msg_by_sequence_number(&anchor, index).await?;
+        let link = msg.link.rel();
+        match msg.body{
+            MessageContent::SignedPacket {pk: _, public_payload, masked_payload } => {
+                let (_, p) = untag_branch_payload(&public_payload.0);
+                let m = masked_payload.0;
+                if p.is_empty() && m.is_empty(){
+                    return Ok(None);
+                }
+                Ok(Some((link.to_string(), p, m)))
+            }
+            _ => Ok(None)
+        }
+    }
+
+    ///
+    /// Check if the keyload grants the access to the reader. The access is revoked when a keyload
+    /// that does not include the reader follows one that did
+    ///
+    async fn check_keyload(&mut self, link: &Address){
+        match self.subscriber.receive_keyload(link).await{
+            Ok(true) => {
+                self.has_access = true;
+                self.revoked = false;
+            }
+            Ok(false) => self.revoked = self.has_access,
+            Err(_) => {}
+        }
     }
 
     async fn fetch_all_msgs(&mut self) -> bool{
         let msgs = self.subscriber.fetch_all_next_msgs().await;
         let mut found = false;
         for msg in msgs {
+            if self.closed{
+                break;
+            }
             let link = msg.link.rel();
             match msg.body{
-                MessageContent::SignedPacket {pk: _, public_payload, masked_payload } => {
-                    let p = public_payload.0;
+                MessageContent::Keyload => {
+                    self.check_keyload(&msg.link).await;
+                }
+                MessageContent::SignedPacket {pk, public_payload, masked_payload } => {
+                    let (branch, p) = untag_branch_payload(&public_payload.0);
                     let m = masked_payload.0;
 
-                    if !p.is_empty() || !m.is_empty(){
-                        self.unread_msgs.push_back((link.to_string(), p, m));
+                    if let Some(branch) = &branch{
+                        self.msg_branches.insert(link.to_string(), branch.clone());
+                    }
+                    if p.is_empty() && m.is_empty(){
+                        continue;
+                    }
+                    // The control msgs can be chunked too, so they are checked once reassembled.
+                    // The chunks of different signers are never mixed, so the signer of the packet is known
+                    let assembler_id = format!("{}:{}", public_key_to_hex(&pk), branch.unwrap_or_default());
+                    let packets = self.assemblers.entry(assembler_id)
+                        .or_insert_with(ChunkAssembler::new)
+                        .push(&link.to_string(), &p, &m);
+                    for packet in packets{
+                        let packet = match packet{
+                            Ok((msg_id, p, m)) => {
+                                if self.is_state_msg(&p, &m) || self.check_close(&p, &m, &pk) || self.check_schema_msg(&p, &m, &pk){
+                                    continue;
+                                }
+                                // The masked payloads sent after a revocation are not readable anymore
+                                let m = match self.revoked{
+                                    true => vec![],
+                                    false => m
+                                };
+                                self.check_conformance(&msg_id, &p);
+                                Ok((msg_id, p, m))
+                            }
+                            Err(e) => Err(e)
+                        };
                         found = true;
+                        self.unread_msgs.push_back(packet);
                     }
                 }
                 _ => {println!("{}", link.to_string());}
             }
         }
+        let _ = self.autosave();
         found
     }
 }
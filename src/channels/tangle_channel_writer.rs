@@ -1,18 +1,29 @@
+use std::collections::{HashMap, VecDeque};
 use std::string::ToString;
 
 use anyhow::Result;
 use iota_streams::{
     app::transport::tangle::client::{Client as StreamsClient, SendOptions},
-    app_channels::api::tangle::Author,
+    app_channels::api::tangle::{Address, Author, PublicKey, Subscriber},
 };
 
 use crate::channels::channel_state::ChannelState;
-use crate::payload::payload_serializers::{RawPacketBuilder, RawPacket};
-use crate::payload::payload_types::{StreamsPacket, StreamsPacketSerializer};
+use crate::payload::payload_serializers::{RawPacketBuilder, RawPacket, JsonSerializer};
+use crate::payload::payload_schema::PacketSchema;
+use crate::payload::payload_recipients::recipient_from_hex;
+use crate::payload::payload_ratchet::KeyRatchet;
+use crate::payload::payload_keystore::KeyStore;
+use crate::channels::state_container::StateChannelType;
+use crate::channels::state_store::{Autosave, StateStore};
+use crate::payload::payload_types::{StreamsPacket, StreamsPacketBuilder, StreamsPacketSerializer};
 use crate::user_builders::author_builder::AuthorBuilder;
-use crate::utility::iota_utility::{create_link, hash_string, msg_index};
+use crate::utility::iota_utility::{create_link, hash_string, msg_index, tag_branch_payload, untag_branch_payload, public_key_from_hex, create_psk, pskid_to_hex, sleep};
+use crate::channels::outbox::{OutboxEntry, RetryOptions};
+use crate::payload::payload_chunks::{decode_header, split_payloads, ChunkAssembler, ChunkHeader, DEFAULT_MAX_PAYLOAD_SIZE};
+use crate::payload::payload_files::{FilePart, FileTransfer};
 use crate::user_builders::subscriber_builder::SubscriberBuilder;
 use iota_streams::app_channels::api::tangle::MessageContent;
+use iota_streams::ddml::types::Bytes;
 use crate::channels::builders::channel_builders::ChannelWriterBuilder;
 
 ///
@@ -22,7 +33,22 @@ pub struct ChannelWriter {
     author: Author<StreamsClient>,
     channel_address: String,
     announcement_id: String,
-    last_msg_id: String
+    last_msg_id: String,
+    branches: HashMap<String, String>,
+    single_depth: bool,
+    subscribers: Vec<String>,
+    psks: HashMap<String, String>,
+    outbox: VecDeque<OutboxEntry>,
+    retry_options: RetryOptions,
+    checkpoint_interval: u32,
+    checkpoint_psw: Option<String>,
+    msgs_since_checkpoint: u32,
+    closed: bool,
+    max_payload_size: usize,
+    schema: Option<PacketSchema>,
+    ratchet: Option<KeyRatchet>,
+    key_store: Option<KeyStore>,
+    autosave: Option<Autosave>,
 }
 
 impl ChannelWriter {
@@ -44,6 +70,21 @@ impl ChannelWriter {
             channel_address,
             announcement_id: String::default(),
             last_msg_id: String::default(),
+            branches: HashMap::new(),
+            single_depth: false,
+            subscribers: vec![],
+            psks: HashMap::new(),
+            outbox: VecDeque::new(),
+            retry_options: RetryOptions::default(),
+            checkpoint_interval: 0,
+            checkpoint_psw: None,
+            msgs_since_checkpoint: 0,
+            closed: false,
+            max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE,
+            schema: None,
+            ratchet: None,
+            key_store: None,
+            autosave: None,
         }
     }
 
@@ -67,6 +108,20 @@ impl ChannelWriter {
         Ok(channel)
     }
 
+    ///
+    /// Restore the channels from the state saved in the store with the specified key
+    ///
+    pub async fn import_from_store(store: &dyn StateStore, key: &str, psw: &str, node_url: Option<&str>, send_options: Option<SendOptions>) -> Result<ChannelWriter>{
+        let channel_state = ChannelState::from_store(store, key, psw)?;
+        let mut channel = ChannelWriter::import(&channel_state, psw, node_url, send_options)?;
+        channel.check_update_state().await;
+        Ok(channel)
+    }
+
+    ///
+    /// Restore the channels from the most recent state checkpoint published by the author of the channel.
+    /// In single depth channels the checkpoint is located by index, without walking the channel
+    ///
     pub async fn import_from_tangle(channel_id: &str, announce_id: &str, state_psw: &str, node_url: Option<&str>, send_options: Option<SendOptions>) -> Result<ChannelWriter>{
         match ChannelWriter::check_state(channel_id, announce_id, node_url).await{
             Ok(state) => ChannelWriter::import_from_bytes(&state, state_psw, node_url, send_options).await,
@@ -91,64 +146,599 @@ impl ChannelWriter {
     ///
     pub async fn open_and_save(&mut self, state_psw: &str) -> Result<(String, String, String)>{
         let res = self.open().await?;
+        let state_msg_id = self.checkpoint(state_psw).await?;
+        Ok((res.0, res.1, state_msg_id))
+    }
 
+    ///
+    /// Publish a checkpoint of the encrypted state of the channels. import_from_tangle restores the most recent one.
+    /// A state bigger than the maximum payload size is sent in chunks
+    ///
+    pub async fn checkpoint(&mut self, state_psw: &str) -> Result<String>{
         let public = format!("{}:{}.state", self.channel_address, self.announcement_id).as_bytes().to_vec();
         let masked = self.export_to_bytes(state_psw)?;
+        let packet = ChannelWriter::raw_packet(public, masked, None)?;
 
-        let state_msg_id = self.send_signed_raw_data(public, masked, None).await?;
+        // The state can exceed the maximum payload size, so the checkpoint is chunked as any other msg
+        let msg_id = self.send_chunked(packet.public_data()?.0, packet.masked_data()?.0, None).await?;
+        self.msgs_since_checkpoint = 0;
+        Ok(msg_id)
+    }
 
-        Ok((res.0, res.1, state_msg_id))
+    ///
+    /// Publish automatically a state checkpoint every `interval` msgs, encrypted with the specified password.
+    /// An interval of 0 disables the automatic checkpoints. Only the interval is included in the exported state,
+    /// so after an import the checkpoints are suspended until the password is set again with this method
+    ///
+    pub fn set_checkpoints(&mut self, interval: u32, state_psw: &str){
+        self.checkpoint_interval = interval;
+        self.checkpoint_psw = match interval{
+            0 => None,
+            _ => Some(state_psw.to_string())
+        };
     }
 
     ///
     /// Write signed packet in a raw format.
     ///
     pub async fn send_signed_raw_data(&mut self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> Result<String> {
-        let link_to = create_link(&self.channel_address, &self.last_msg_id)?;
-        let packet = match key_nonce{
-            None => RawPacketBuilder::new()
-                .public(&p_data)?
-                .masked(&m_data)?
-                .build(),
-            Some((key, nonce)) => RawPacketBuilder::new()
-                .public(&p_data)?
-                .masked(&m_data)?
-                .key_nonce(&key, &nonce)
-                .build()
+        let packet = ChannelWriter::raw_packet(p_data, m_data, key_nonce)?;
+        self.send_signed_packet(&packet).await
+    }
+
+    ///
+    /// Write signed packet in a raw format, encrypting the masked data for the specified hex encoded X25519 public keys
+    ///
+    pub async fn send_signed_raw_data_for_recipients(&mut self, p_data: Vec<u8>, m_data: Vec<u8>, recipients: &[String]) -> Result<String> {
+        let mut keys = Vec::with_capacity(recipients.len());
+        for recipient in recipients{
+            keys.push(recipient_from_hex(recipient)?);
+        }
+        let packet = RawPacketBuilder::new()
+            .public(&p_data)?
+            .masked(&m_data)?
+            .recipients(&keys)
+            .build();
+        self.send_signed_packet(&packet).await
+    }
+
+    ///
+    /// Write signed packet in a raw format, encrypting the masked data with the next key of the ratchet
+    ///
+    pub async fn send_signed_raw_data_ratcheted(&mut self, p_data: Vec<u8>, m_data: Vec<u8>) -> Result<String> {
+        let mut builder = RawPacketBuilder::new();
+        builder.public(&p_data)?
+            .masked(&m_data)?;
+        self.send_ratcheted_packet(&mut builder).await
+    }
+
+    ///
+    /// Build the packet encrypting its masked data with the next key of the ratchet and send it.
+    /// The ratchet moves forward even if the msg can't be sent, so a key is never used twice
+    ///
+    pub async fn send_ratcheted_packet<T>(&mut self, builder: &mut StreamsPacketBuilder<T>) -> Result<String>
+    where
+        T: StreamsPacketSerializer,
+    {
+        let ratchet = match self.ratchet.as_mut(){
+            None => return Err(anyhow::Error::msg("The channel has no ratchet")),
+            Some(ratchet) => ratchet
         };
+        let packet = builder.ratchet(ratchet).build();
+        self.send_signed_packet(&packet).await
+    }
 
-        let ret_link = self.author.send_signed_packet(
-            &link_to,
-            &packet.public_data()?,
-            &packet.masked_data()?,
-        ).await?;
+    ///
+    /// Write signed packet in a raw format, encrypting the masked data with a new key of the key store
+    ///
+    pub async fn send_signed_raw_data_shreddable(&mut self, p_data: Vec<u8>, m_data: Vec<u8>) -> Result<String> {
+        let mut builder = RawPacketBuilder::new();
+        builder.public(&p_data)?
+            .masked(&m_data)?;
+        self.send_shreddable_packet(&mut builder).await
+    }
 
-        let msg_id = ret_link.0.msgid.to_string();
-        self.last_msg_id = msg_id.clone();
-        Ok(msg_id)
+    ///
+    /// Build the packet encrypting its masked data with a new key of the key store and send it.
+    /// The key is linked to the id of the sent msg, so it can be erased with erase_msg_key
+    ///
+    pub async fn send_shreddable_packet<T>(&mut self, builder: &mut StreamsPacketBuilder<T>) -> Result<String>
+    where
+        T: StreamsPacketSerializer,
+    {
+        let key_store = match self.key_store.as_mut(){
+            None => return Err(anyhow::Error::msg("The channel has no key store")),
+            Some(key_store) => key_store
+        };
+        let packet = builder.shreddable(key_store).build();
+        let key_id = packet.shreddable_key_id().unwrap_or_default();
+
+        let pending = self.outbox.len();
+        let res = self.send_signed_packet(&packet).await;
+        let queued = self.outbox.len() > pending;
+        if let Some(key_store) = self.key_store.as_mut(){
+            match &res{
+                Ok(msg_id) => key_store.link_msg(msg_id, &key_id),
+                // The key of a queued packet is linked to its msg when the outbox is flushed
+                Err(_) if queued => {},
+                Err(_) => {
+                    key_store.erase_key(&key_id);
+                }
+            }
+        }
+        res
     }
 
     ///
-    /// Write signed packet with formatted data.
+    /// Write signed packet with formatted data. Packets bigger than the maximum payload size are sent
+    /// as a manifest msg followed by the chunk msgs, and the id of the manifest is returned.
+    /// Json packets are validated against the schema of the channel, if any.
+    /// If the packet can't be sent and the retry options enable queue_on_failure, it is put in the outbox
+    /// and an error is returned, so that it can be sent later by flush_outbox
     ///
     pub async fn send_signed_packet<T>(&mut self, packet: &StreamsPacket<T>) -> Result<String>
     where
         T: StreamsPacketSerializer,
     {
-        let link_to = create_link(&self.channel_address, &self.last_msg_id)?;
-        let (public_payload, masked_payload) = (packet.public_data()?, packet.masked_data()?);
+        self.check_schema(packet)?;
+        let entry = OutboxEntry::new(packet.public_data()?.0, packet.masked_data()?.0, None)
+            .with_key_id(packet.shreddable_key_id());
+        self.send_or_queue(entry).await
+    }
 
-        let ret_link = self.author.send_signed_packet(
-            &link_to,
-            &public_payload,
-            &masked_payload,
-        ).await?;
+    ///
+    /// Set the maximum size in bytes of the payloads of a single msg. It is included in the exported state
+    ///
+    pub fn set_max_payload_size(&mut self, max_payload_size: usize){
+        self.max_payload_size = max_payload_size;
+    }
+
+    ///
+    /// Upload a file in the channel, sending its manifest followed by the chunks not confirmed yet.
+    /// If the upload is interrupted, the transfer keeps its progress and it can be resumed calling this method again.
+    /// Before the manifest is sent, the chunk size is reduced so that each chunk msg fits in the maximum payload size.
+    /// The progress callback receives the number of confirmed chunks and the total number of chunks
+    ///
+    /// # Return Value
+    /// It returns the id of the manifest msg, that identifies the file in the channel
+    ///
+    pub async fn send_file<F>(&mut self, transfer: &mut FileTransfer, key_nonce: Option<([u8;32], [u8;24])>, mut progress: F) -> Result<String>
+    where
+        F: FnMut(u32, u32),
+    {
+        while self.send_next_file_part(transfer, &key_nonce).await?{
+            progress(transfer.sent_chunks(), transfer.chunks());
+        }
+
+        match transfer.manifest_msg_id(){
+            None => Err(anyhow::Error::msg("The file manifest has not been sent")),
+            Some(msg_id) => Ok(msg_id)
+        }
+    }
+
+    ///
+    /// Send the next part of a file upload: the manifest, if it has not been confirmed yet, or the next chunk
+    ///
+    /// # Return Value
+    /// It returns false if the manifest and all the chunks have already been confirmed
+    ///
+    pub async fn send_next_file_part(&mut self, transfer: &mut FileTransfer, key_nonce: &Option<([u8;32], [u8;24])>) -> Result<bool>{
+        if transfer.manifest_msg_id().is_none(){
+            transfer.fit_chunk_size(self.max_file_chunk_size(&transfer.transfer_id(), key_nonce)?);
+            let msg_id = self.send_file_part(&transfer.manifest(), key_nonce).await?;
+            transfer.confirm_manifest(&msg_id);
+            return Ok(true);
+        }
+        match transfer.next_chunk(){
+            None => Ok(false),
+            Some(chunk) => {
+                self.send_file_part(&chunk, key_nonce).await?;
+                transfer.confirm_chunk();
+                Ok(true)
+            }
+        }
+    }
+
+    ///
+    /// Write a batch of signed packets in raw format, in order, using the same key_nonce for all of them.
+    ///
+    /// # Return Value
+    /// It returns a Vector with the result of each packet (msg_id or error)
+    ///
+    pub async fn send_raw_batch(&mut self, data: Vec<(Vec<u8>, Vec<u8>)>, key_nonce: Option<([u8;32], [u8;24])>) -> Vec<Result<String>> {
+        let mut results = Vec::with_capacity(data.len());
+        for (p_data, m_data) in data{
+            let res = self.send_signed_raw_data(p_data, m_data, key_nonce.clone()).await;
+            results.push(res);
+        }
+        results
+    }
+
+    ///
+    /// Write a batch of signed packets with formatted data, in order. A failed packet does not stop the batch:
+    /// the next ones are linked to the last successfully sent msg, so the writer state is always consistent.
+    /// With queue_on_failure, the failed packet and the following ones are queued in the outbox in order.
+    ///
+    /// # Return Value
+    /// It returns a Vector with the result of each packet (msg_id or error)
+    ///
+    pub async fn send_batch<T>(&mut self, packets: &[StreamsPacket<T>]) -> Vec<Result<String>>
+    where
+        T: StreamsPacketSerializer,
+    {
+        let mut results = Vec::with_capacity(packets.len());
+        for packet in packets{
+            results.push(self.send_signed_packet(packet).await);
+        }
+        results
+    }
+
+    ///
+    /// Accept the subscription request sent by a reader.
+    /// The public key is taken from the processed subscription, so it cannot be spoofed by the caller
+    ///
+    /// # Return Value
+    /// It returns the hex encoding of the public key of the new subscriber
+    ///
+    pub async fn accept_subscriber(&mut self, subscription_id: &str) -> Result<String> {
+        let link = create_link(&self.channel_address, subscription_id)?;
+        let known_pks = self.known_public_keys()?;
+        self.author.receive_subscribe(&link).await?;
+        let new_pks: Vec<String> = self.known_public_keys()?.into_iter()
+            .filter(|pk| !known_pks.contains(pk))
+            .collect();
+        let subscriber_pk = match new_pks.as_slice(){
+            [pk] => pk.clone(),
+            [] => return Err(anyhow::Error::msg("The subscription has already been accepted")),
+            _ => return Err(anyhow::Error::msg("Unable to identify the public key of the subscriber")),
+        };
+        public_key_from_hex(&subscriber_pk)?;
+        if !self.subscribers.iter().any(|s| *s == subscriber_pk){
+            self.subscribers.push(subscriber_pk.clone());
+        }
+        Ok(subscriber_pk)
+    }
 
+    ///
+    /// Send a keyload that grants the access to the masked payloads of the next msgs only to the
+    /// specified subscribers. It returns the id of the keyload msg
+    ///
+    pub async fn send_keyload(&mut self, subscribers: &[String]) -> Result<String> {
+        self.send_keyload_for(subscribers, &[]).await
+    }
+
+    ///
+    /// Store a pre shared key derived from the specified seed. It returns the hex encoded id of the key
+    ///
+    pub fn add_psk(&mut self, psk_seed: &str) -> String {
+        let (pskid, psk) = create_psk(psk_seed);
+        let pskid_hex = pskid_to_hex(&pskid);
+        self.author.store_psk(pskid, psk);
+        self.psks.insert(pskid_hex.clone(), psk_seed.to_string());
+        pskid_hex
+    }
+
+    ///
+    /// Remove a pre shared key, so that it can no longer be used in the next keyloads
+    ///
+    pub fn remove_psk(&mut self, pskid: &str) -> Result<()> {
+        match self.psks.remove(pskid){
+            None => Err(anyhow::Error::msg(format!("Pre shared key {} does not exist", pskid))),
+            Some(_) => Ok(())
+        }
+    }
+
+    ///
+    /// Send a keyload that grants the access to the masked payloads of the next msgs only to the
+    /// owners of the specified pre shared keys. It returns the id of the keyload msg
+    ///
+    pub async fn send_psk_keyload(&mut self, pskids: &[String]) -> Result<String> {
+        self.send_keyload_for(&[], pskids).await
+    }
+
+    ///
+    /// Revoke the access of the specified subscribers and pre shared keys, sending a keyload for all the
+    /// remaining subscribers and pre shared keys, so the revoked readers are not able to read the masked
+    /// payloads of the next msgs. The readers detect the revocation from the keyload itself, so the revoked
+    /// ids are not disclosed to the other readers.
+    /// It returns the id of the new keyload msg
+    ///
+    pub async fn revoke_access(&mut self, subscribers: &[String], pskids: &[String]) -> Result<String> {
+        for sub in subscribers{
+            if !self.subscribers.contains(sub){
+                return Err(anyhow::Error::msg(format!("Subscriber {} has not been accepted", sub)));
+            }
+        }
+        for pskid in pskids{
+            if !self.psks.contains_key(pskid){
+                return Err(anyhow::Error::msg(format!("Pre shared key {} does not exist", pskid)));
+            }
+        }
+
+        self.subscribers.retain(|s| !subscribers.contains(s));
+        for pskid in pskids{
+            self.psks.remove(pskid);
+        }
+        let remaining_subs = self.subscribers.clone();
+        let remaining_psks: Vec<String> = self.psks.keys().cloned().collect();
+        self.send_keyload_for(&remaining_subs, &remaining_psks).await
+    }
+
+    ///
+    /// Get the hex encoded ids of the stored pre shared keys
+    ///
+    pub fn psks(&self) -> Vec<String>{
+        self.psks.keys().cloned().collect()
+    }
+
+    ///
+    /// Get the public keys of the accepted subscribers
+    ///
+    pub fn subscribers(&self) -> Vec<String>{
+        self.subscribers.clone()
+    }
+
+    ///
+    /// Create a new branch starting from the announcement. It is available only in multi branch channels.
+    /// It returns the id of the keyload message that roots the branch
+    ///
+    pub async fn create_branch(&mut self, branch: &str) -> Result<String> {
+        self.check_open()?;
+        if !self.author.is_multi_branching(){
+            return Err(anyhow::Error::msg("Branches are available only in multi branch channels"));
+        }
+        if branch.is_empty() || branch.contains('#'){
+            return Err(anyhow::Error::msg("Branch name must be non empty and cannot contain '#'"));
+        }
+        if self.branches.contains_key(branch){
+            return Err(anyhow::Error::msg(format!("Branch {} already exists", branch)));
+        }
+
+        let link_to = create_link(&self.channel_address, &self.announcement_id)?;
+        let ret_link = self.author.send_keyload_for_everyone(&link_to).await?;
         let msg_id = ret_link.0.msgid.to_string();
+        self.branches.insert(branch.to_string(), msg_id.clone());
+        let _ = self.autosave();
+        Ok(msg_id)
+    }
+
+    ///
+    /// Write signed packet in a raw format in the specified branch.
+    ///
+    pub async fn send_signed_raw_data_to_branch(&mut self, branch: &str, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> Result<String> {
+        let packet = ChannelWriter::raw_packet(p_data, m_data, key_nonce)?;
+        self.send_signed_packet_to_branch(branch, &packet).await
+    }
+
+    ///
+    /// Write signed packet with formatted data in the specified branch.
+    ///
+    pub async fn send_signed_packet_to_branch<T>(&mut self, branch: &str, packet: &StreamsPacket<T>) -> Result<String>
+    where
+        T: StreamsPacketSerializer,
+    {
+        if !self.branches.contains_key(branch){
+            return Err(anyhow::Error::msg(format!("Branch {} does not exist", branch)));
+        }
+        self.check_schema(packet)?;
+        let entry = OutboxEntry::new(packet.public_data()?.0, packet.masked_data()?.0, Some(branch.to_string()))
+            .with_key_id(packet.shreddable_key_id());
+        self.send_or_queue(entry).await
+    }
+
+    ///
+    /// Put a signed packet with formatted data in the outbox. The queued packets are sent by flush_outbox
+    /// and are included in the exported state, so they survive restarts. With autosave enabled, the state
+    /// is saved as soon as the packet is queued
+    ///
+    pub fn queue_signed_packet<T>(&mut self, packet: &StreamsPacket<T>) -> Result<()>
+    where
+        T: StreamsPacketSerializer,
+    {
+        self.check_schema(packet)?;
+        let entry = OutboxEntry::new(packet.public_data()?.0, packet.masked_data()?.0, None)
+            .with_key_id(packet.shreddable_key_id());
+        self.queue(entry);
+        Ok(())
+    }
+
+    ///
+    /// Put a signed packet in a raw format in the outbox
+    ///
+    pub fn queue_signed_raw_data(&mut self, p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> Result<()> {
+        let packet = ChannelWriter::raw_packet(p_data, m_data, key_nonce)?;
+        self.queue_signed_packet(&packet)
+    }
+
+    ///
+    /// Put a signed packet with formatted data for the specified branch in the outbox
+    ///
+    pub fn queue_signed_packet_to_branch<T>(&mut self, branch: &str, packet: &StreamsPacket<T>) -> Result<()>
+    where
+        T: StreamsPacketSerializer,
+    {
+        if !self.branches.contains_key(branch){
+            return Err(anyhow::Error::msg(format!("Branch {} does not exist", branch)));
+        }
+        self.check_schema(packet)?;
+        let entry = OutboxEntry::new(packet.public_data()?.0, packet.masked_data()?.0, Some(branch.to_string()))
+            .with_key_id(packet.shreddable_key_id());
+        self.queue(entry);
+        Ok(())
+    }
+
+    ///
+    /// Send the queued packets in order. Each packet is retried with exponential backoff and
+    /// the flush stops at the first packet that can't be sent, so the order is always preserved.
+    /// A chunked packet that fails halfway is sent again from its manifest.
+    ///
+    /// # Return Value
+    /// It returns a Vector with the result of each attempted packet: the sent msg_ids followed by
+    /// the error of the packet that is still in the outbox, if any
+    ///
+    pub async fn flush_outbox(&mut self) -> Vec<Result<String>> {
+        let mut results = vec![];
+        let mut attempt = 0;
+        while let Some(res) = self.flush_next().await{
+            match res{
+                Ok(msg_id) => {
+                    results.push(Ok(msg_id));
+                    attempt = 0;
+                }
+                Err(e) if attempt >= self.retry_options.max_retries => {
+                    results.push(Err(e));
+                    break;
+                }
+                Err(_) => {
+                    sleep(self.retry_options.delay(attempt)).await;
+                    attempt += 1;
+                }
+            }
+        }
+        results
+    }
+
+    ///
+    /// Try once to send the first packet of the outbox, removing it from the outbox if it is sent.
+    /// The retries are left to the caller, so the writer is not held while waiting between them
+    ///
+    /// # Return Value
+    /// It returns the msg_id of the sent packet, the error of the attempt or None if the outbox is empty
+    ///
+    pub async fn flush_next(&mut self) -> Option<Result<String>> {
+        let entry = self.outbox.front().cloned()?;
+        let msg_id = match self.send_outbox_entry(&entry).await{
+            Ok(msg_id) => msg_id,
+            Err(e) => return Some(Err(e))
+        };
+        // The entry is removed before a checkpoint can export the outbox, so it is never sent twice
+        self.outbox.pop_front();
+        // The key of a queued shreddable packet can be erased by msg id only from now on
+        if let (Some(key_id), Some(key_store)) = (entry.key_id(), self.key_store.as_mut()){
+            key_store.link_msg(&msg_id, key_id);
+        }
+        self.on_msg_sent().await;
+        Some(Ok(msg_id))
+    }
+
+    ///
+    /// Get the number of packets waiting in the outbox
+    ///
+    pub fn pending_msgs(&self) -> usize{
+        self.outbox.len()
+    }
+
+    ///
+    /// Set the retry policy used by flush_outbox and the queueing of the packets that can't be sent.
+    /// It is included in the exported state
+    ///
+    pub fn set_retry_options(&mut self, retry_options: RetryOptions){
+        self.retry_options = retry_options;
+    }
+
+    pub fn retry_options(&self) -> RetryOptions{
+        self.retry_options.clone()
+    }
+
+    ///
+    /// Set the local store of the per msg keys. It is not part of the exported state, because
+    /// the state can be published in the channel, so it must be saved separately
+    ///
+    pub fn set_key_store(&mut self, key_store: Option<KeyStore>){
+        self.key_store = key_store;
+    }
+
+    pub fn key_store(&self) -> Option<&KeyStore>{
+        self.key_store.as_ref()
+    }
+
+    ///
+    /// Erase the key of a msg from the key store, making its masked payload permanently unreadable
+    ///
+    pub fn erase_msg_key(&mut self, msg_id: &str) -> Result<()>{
+        match self.key_store.as_mut().map(|key_store| key_store.erase_msg(msg_id)){
+            Some(true) => Ok(()),
+            _ => Err(anyhow::Error::msg(format!("There is no key for msg {}", msg_id)))
+        }
+    }
+
+    ///
+    /// Enable the forward secret ratchet of the masked payloads, seeded from the channel secret
+    ///
+    pub fn set_ratchet(&mut self, channel_secret: &str){
+        self.ratchet = Some(KeyRatchet::new(channel_secret));
+    }
+
+    ///
+    /// Export the ratchet at its current position. A reader that gets it can read only the msgs sent from now on
+    ///
+    pub fn export_ratchet(&self) -> Option<String>{
+        self.ratchet.as_ref().map(|ratchet| ratchet.to_hex())
+    }
+
+    ///
+    /// Set the JSON Schema used to validate the json packets before sending them. None disables the validation
+    ///
+    pub fn set_schema(&mut self, schema: Option<PacketSchema>){
+        self.schema = schema;
+    }
+
+    pub fn schema(&self) -> Option<PacketSchema>{
+        self.schema.clone()
+    }
+
+    ///
+    /// Publish the schema of the channel, so that the readers can validate the json packets they receive.
+    /// It returns the id of the schema msg
+    ///
+    pub async fn announce_schema(&mut self) -> Result<String>{
+        let schema = match &self.schema{
+            None => return Err(anyhow::Error::msg("The channel has no schema")),
+            Some(schema) => schema.clone()
+        };
+        let packet = RawPacketBuilder::new()
+            .public(&format!("{}:{}.schema", self.channel_address, self.announcement_id))?
+            .masked(&schema)?
+            .build();
+        self.send_signed_packet(&packet).await
+    }
+
+    ///
+    /// Close the channel publishing a signed end of stream msg, optionally pointing to the (channel_id, announce_id)
+    /// of a successor channel. No msg can be sent after the channel is closed, so the channel can't be closed
+    /// while the outbox has pending msgs. It returns the id of the end of stream msg
+    ///
+    pub async fn close(&mut self, successor: Option<(String, String)>) -> Result<String>{
+        if !self.outbox.is_empty(){
+            return Err(anyhow::Error::msg("The outbox has pending msgs, flush it before closing the channel"));
+        }
+        let packet = RawPacketBuilder::new()
+            .public(&format!("{}:{}.close", self.channel_address, self.announcement_id))?
+            .masked(&successor)?
+            .build();
+
+        let link_to_id = self.link_to_id();
+        let msg_id = self.send_payloads(&link_to_id, packet.public_data()?, packet.masked_data()?).await?;
         self.last_msg_id = msg_id.clone();
+        self.closed = true;
+        let _ = self.autosave();
         Ok(msg_id)
     }
 
+    ///
+    /// Check if the channel has been closed
+    ///
+    pub fn is_closed(&self) -> bool{
+        self.closed
+    }
+
+    ///
+    /// Get the names of the branches created in the channel
+    ///
+    pub fn branches(&self) -> Vec<String>{
+        self.branches.keys().cloned().collect()
+    }
+
     ///
     /// Export the channels state into an encrypted byte array.
     ///
@@ -166,6 +756,33 @@ impl ChannelWriter {
         Ok(())
     }
 
+    ///
+    /// Stores the channels state in the store with the specified key
+    ///
+    pub fn export_to_store(&self, store: &mut dyn StateStore, key: &str, psw: &str) -> Result<()>{
+        let channel_state = self.export(psw)?;
+        channel_state.write_to_store(store, key, psw)
+    }
+
+    ///
+    /// Save the state in the store after each sent or queued msg. A failed save does not affect the msg,
+    /// its error is kept by last_autosave_error and the state is saved again after the next one
+    ///
+    pub fn set_autosave(&mut self, store: Box<dyn StateStore>, key: &str, psw: &str){
+        self.autosave = Some(Autosave::new(store, key, psw));
+    }
+
+    pub fn disable_autosave(&mut self){
+        self.autosave = None;
+    }
+
+    ///
+    /// Get the error of the last autosave, if it failed. It is cleared by the next successful save
+    ///
+    pub fn last_autosave_error(&self) -> Option<String>{
+        self.autosave.as_ref().and_then(|autosave| autosave.last_error())
+    }
+
     ///
     /// Get the channels address and the announcement id
     ///
@@ -180,23 +797,272 @@ impl ChannelWriter {
         let addr = create_link(&self.channel_address, msg_id)?;
         Ok(msg_index(&addr))
     }
+
+    ///
+    /// Check if the messages are all linked to the announcement and addressable by their sequence number
+    ///
+    pub fn is_single_depth(&self) -> bool{
+        self.single_depth
+    }
+
+    pub(crate) fn set_single_depth(&mut self, single_depth: bool){
+        self.single_depth = single_depth;
+    }
 }
 
 impl ChannelWriter{
+    fn known_public_keys(&self) -> Result<Vec<String>>{
+        Ok(self.author.fetch_state()?.into_iter()
+            .map(|(pk, _)| pk)
+            .collect())
+    }
+
+    ///
+    /// Move the main chain and the branch heads to the msgs published after the exported state.
+    /// In multi branch channels each round returns a msg for each branch, so all of them are checked.
+    /// The tagged msgs move the head of their branch, registering the branches created after the export,
+    /// while the untagged msgs move the main chain only if they are linked to its head, so the keyloads
+    /// that root the new branches are skipped
+    ///
     async fn check_update_state(&mut self){
         loop{
-            let mut msgs = self.author.fetch_next_msgs().await;
+            let msgs = self.author.fetch_next_msgs().await;
             if msgs.is_empty(){break;}
-            let last_msg = match msgs.pop(){
-                None => return,
-                Some(m) => m
+            for msg in msgs{
+                let msg_id = msg.link.msgid.to_string();
+                // The keyloads that root the branches are already known heads
+                if self.branches.values().any(|head| *head == msg_id){
+                    continue;
+                }
+                let branch = match &msg.body{
+                    MessageContent::SignedPacket { public_payload, .. } => untag_branch_payload(&public_payload.0).0,
+                    _ => None
+                };
+                match branch{
+                    Some(branch) => {
+                        self.branches.insert(branch, msg_id);
+                    }
+                    None if self.single_depth || msg.prev_link.msgid.to_string() == self.last_msg_id => {
+                        self.last_msg_id = msg_id;
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    async fn send_keyload_for(&mut self, subscribers: &[String], pskids: &[String]) -> Result<String> {
+        self.check_open()?;
+        let mut ke_pks = vec![];
+        for sub in subscribers{
+            if !self.subscribers.contains(sub){
+                return Err(anyhow::Error::msg(format!("Subscriber {} has not been accepted", sub)));
+            }
+            ke_pks.push(public_key_from_hex(sub)?);
+        }
+        let mut psk_ids = vec![];
+        for pskid in pskids{
+            match self.psks.get(pskid){
+                None => return Err(anyhow::Error::msg(format!("Pre shared key {} does not exist", pskid))),
+                Some(psk_seed) => psk_ids.push(create_psk(psk_seed).0)
+            }
+        }
+
+        let link_to = create_link(&self.channel_address, &self.link_to_id())?;
+        let ret_link = self.author.send_keyload(&link_to, &psk_ids, &ke_pks).await?;
+        let msg_id = ret_link.0.msgid.to_string();
+        self.last_msg_id = msg_id.clone();
+        let _ = self.autosave();
+        Ok(msg_id)
+    }
+
+    async fn send_file_part(&mut self, part: &FilePart, key_nonce: &Option<([u8;32], [u8;24])>) -> Result<String>{
+        let packet = self.file_packet(part, key_nonce)?;
+        self.send_signed_packet(&packet).await
+    }
+
+    fn file_packet(&self, part: &FilePart, key_nonce: &Option<([u8;32], [u8;24])>) -> Result<RawPacket>{
+        let mut builder = RawPacketBuilder::new();
+        builder.public(&format!("{}:{}.file", self.channel_address, self.announcement_id))?
+            .masked(part)?
+            .compact(true);
+        if let Some((key, nonce)) = key_nonce{
+            builder.key_nonce(key, nonce);
+        }
+        Ok(builder.build())
+    }
+
+    ///
+    /// Get the biggest chunk of a file whose msg fits in the maximum payload size, once the packet is encoded.
+    /// The encoding of the file packets grows linearly with the data, so the overhead is measured on an empty chunk
+    ///
+    fn max_file_chunk_size(&self, transfer_id: &str, key_nonce: &Option<([u8;32], [u8;24])>) -> Result<usize>{
+        let empty = FilePart::Chunk{ transfer_id: transfer_id.to_string(), index: u32::MAX, data: vec![] };
+        let packet = self.file_packet(&empty, key_nonce)?;
+        let overhead = packet.public_data()?.0.len() + packet.masked_data()?.0.len();
+        match self.max_payload_size.checked_sub(overhead){
+            Some(size) if size > 0 => Ok(size),
+            _ => Err(anyhow::Error::msg("The maximum payload size is too small for the file chunks"))
+        }
+    }
+
+    fn link_to_id(&self) -> String{
+        match self.single_depth{
+            true => self.announcement_id.clone(),
+            false => self.last_msg_id.clone()
+        }
+    }
+
+    fn raw_packet(p_data: Vec<u8>, m_data: Vec<u8>, key_nonce: Option<([u8;32], [u8;24])>) -> Result<RawPacket>{
+        let packet = match key_nonce{
+            None => RawPacketBuilder::new()
+                .public(&p_data)?
+                .masked(&m_data)?
+                .build(),
+            Some((key, nonce)) => RawPacketBuilder::new()
+                .public(&p_data)?
+                .masked(&m_data)?
+                .key_nonce(&key, &nonce)
+                .build()
+        };
+        Ok(packet)
+    }
+
+    async fn send_outbox_entry(&mut self, entry: &OutboxEntry) -> Result<String>{
+        self.send_chunked(entry.public().to_vec(), entry.masked().to_vec(), entry.branch()).await
+    }
+
+    ///
+    /// Send the entry or, with queue_on_failure, put it in the outbox if it can't be sent.
+    /// While the outbox is not empty the new entries are queued behind the pending ones, to preserve the order
+    ///
+    async fn send_or_queue(&mut self, entry: OutboxEntry) -> Result<String>{
+        self.check_open()?;
+        if !self.retry_options.queue_on_failure{
+            let msg_id = self.send_outbox_entry(&entry).await?;
+            self.on_msg_sent().await;
+            return Ok(msg_id);
+        }
+        if !self.outbox.is_empty(){
+            self.queue(entry);
+            return Err(anyhow::Error::msg("The msg has been queued behind the pending msgs of the outbox"));
+        }
+        match self.send_outbox_entry(&entry).await{
+            Ok(msg_id) => {
+                self.on_msg_sent().await;
+                Ok(msg_id)
+            }
+            Err(e) => {
+                self.queue(entry);
+                Err(anyhow::Error::msg(format!("The msg has been queued in the outbox: {}", e)))
+            }
+        }
+    }
+
+    fn queue(&mut self, entry: OutboxEntry){
+        self.outbox.push_back(entry);
+        let _ = self.autosave();
+    }
+
+    ///
+    /// Send the payloads in the main chain or in the specified branch, splitting them in chunks if needed.
+    /// The writer state is updated after each msg, so it is consistent even if a chunk fails
+    ///
+    async fn send_chunked(&mut self, public: Vec<u8>, masked: Vec<u8>, branch: Option<&str>) -> Result<String>{
+        // The branch tag is added to each msg, so it is not available for the payloads
+        let tag_size = branch.map(|branch| tag_branch_payload(branch, &[]).len()).unwrap_or(0);
+        let max_size = match self.max_payload_size.checked_sub(tag_size){
+            None => return Err(anyhow::Error::msg("The maximum payload size is smaller than the branch tag")),
+            Some(max_size) => max_size
+        };
+        let msgs = split_payloads(&public, &masked, max_size)?;
+        let mut first_msg_id = None;
+        for (p, m) in msgs{
+            let (link_to_id, p) = match branch{
+                None => (self.link_to_id(), p),
+                Some(branch) => match self.branches.get(branch){
+                    None => return Err(anyhow::Error::msg(format!("Branch {} does not exist", branch))),
+                    Some(head) => (head.clone(), tag_branch_payload(branch, &p))
+                }
             };
-            self.last_msg_id = last_msg.link.msgid.to_string();
+            let msg_id = self.send_payloads(&link_to_id, Bytes(p), Bytes(m)).await?;
+            match branch{
+                None => self.last_msg_id = msg_id.clone(),
+                Some(branch) => {
+                    self.branches.insert(branch.to_string(), msg_id.clone());
+                }
+            }
+            first_msg_id.get_or_insert(msg_id);
+        }
+        match first_msg_id{
+            None => Err(anyhow::Error::msg("No msg has been sent")),
+            Some(msg_id) => Ok(msg_id)
+        }
+    }
+
+    ///
+    /// Publish a checkpoint when the configured interval is reached. A failed checkpoint is retried
+    /// after the next msg, without affecting the msg that has just been sent
+    ///
+    async fn on_msg_sent(&mut self){
+        self.msgs_since_checkpoint += 1;
+        if let Some(psw) = self.checkpoint_psw.clone(){
+            if self.checkpoint_interval > 0 && self.msgs_since_checkpoint >= self.checkpoint_interval{
+                let _ = self.checkpoint(&psw).await;
+            }
+        }
+        let _ = self.autosave();
+    }
+
+    fn autosave(&mut self) -> Result<()>{
+        let state = match &self.autosave{
+            None => return Ok(()),
+            Some(autosave) => self.export(autosave.psw())
+        };
+        match self.autosave.as_mut(){
+            None => Ok(()),
+            Some(autosave) => autosave.save(state)
+        }
+    }
+
+    fn check_schema<T>(&self, packet: &StreamsPacket<T>) -> Result<()>
+    where
+        T: StreamsPacketSerializer,
+    {
+        match &self.schema{
+            Some(schema) if T::SERIALIZER_ID == JsonSerializer::SERIALIZER_ID => schema.validate_packet(packet),
+            _ => Ok(())
+        }
+    }
+
+    fn channel_type(&self) -> StateChannelType{
+        match (self.single_depth, self.author.is_multi_branching()){
+            (true, _) => StateChannelType::SingleDepth,
+            (false, true) => StateChannelType::MultiBranch,
+            (false, false) => StateChannelType::SingleBranch,
+        }
+    }
+
+    fn check_open(&self) -> Result<()>{
+        match self.closed{
+            true => Err(anyhow::Error::msg("The channel has been closed")),
+            false => Ok(())
         }
     }
 
+    async fn send_payloads(&mut self, link_to_id: &str, public_payload: Bytes, masked_payload: Bytes) -> Result<String>{
+        self.check_open()?;
+        let link_to = create_link(&self.channel_address, link_to_id)?;
+        let ret_link = self.author.send_signed_packet(
+            &link_to,
+            &public_payload,
+            &masked_payload,
+        ).await?;
+        Ok(ret_link.0.msgid.to_string())
+    }
+
     fn import(channel_state: &ChannelState, psw: &str, node_url: Option<&str>, send_options: Option<SendOptions>) -> Result<ChannelWriter>{
-        let author = AuthorBuilder::build_from_state(
+        let mut author = AuthorBuilder::build_from_state(
             &channel_state.user_state(),
             psw,
             node_url,
@@ -204,42 +1070,177 @@ impl ChannelWriter{
         )?;
         let channel_address = author.channel_address().unwrap().to_string();
 
+        let psks = channel_state.psks();
+        for psk_seed in psks.values(){
+            let (pskid, psk) = create_psk(psk_seed);
+            author.store_psk(pskid, psk);
+        }
+
         Ok(ChannelWriter {
             author,
             channel_address,
             announcement_id: channel_state.announcement_id(),
             last_msg_id: channel_state.last_msg_id(),
+            branches: channel_state.branches(),
+            single_depth: channel_state.single_depth(),
+            subscribers: channel_state.subscribers(),
+            psks,
+            outbox: channel_state.outbox().into_iter().collect(),
+            retry_options: channel_state.retry_options(),
+            checkpoint_interval: channel_state.checkpoint_interval(),
+            checkpoint_psw: None,
+            msgs_since_checkpoint: channel_state.msgs_since_checkpoint(),
+            closed: channel_state.closed(),
+            max_payload_size: channel_state.max_payload_size(),
+            schema: channel_state.schema(),
+            ratchet: channel_state.ratchet(),
+            key_store: None,
+            autosave: None,
         })
     }
 
     fn export(&self, psw: &str) -> Result<ChannelState>{
         let psw_hash = hash_string(psw);
         let author_state = self.author.export(&psw_hash)?;
-        Ok(ChannelState::new(&author_state, &self.channel_address, &self.announcement_id, &self.last_msg_id))
+        Ok(ChannelState::new(&author_state, &self.channel_address, &self.announcement_id, &self.last_msg_id)
+            .with_branches(&self.branches)
+            .with_single_depth(self.single_depth)
+            .with_subscribers(&self.subscribers)
+            .with_psks(&self.psks)
+            .with_outbox(&self.outbox.iter().cloned().collect::<Vec<OutboxEntry>>())
+            .with_closed(self.closed)
+            .with_schema(self.schema.clone())
+            .with_ratchet(self.ratchet.clone())
+            .with_channel_type(self.channel_type())
+            .with_retry_options(&self.retry_options)
+            .with_checkpoints(self.checkpoint_interval, self.msgs_since_checkpoint)
+            .with_max_payload_size(self.max_payload_size))
     }
 
+    ///
+    /// Find the most recent state checkpoint signed by the author of the channel.
+    /// The msgs of single depth channels are addressable by index, so the checkpoint is searched backward
+    /// from the last msg. The msgs of the other channels are linked to each other and can only be read
+    /// in order from the announcement, so they are walked only if no checkpoint is found by index
+    ///
     async fn check_state(channel_id: &str, announce_id: &str, node_url: Option<&str>) -> Result<Vec<u8>>{
         let mut subscriber = match node_url{
             None => SubscriberBuilder::new().build(),
             Some(node) => SubscriberBuilder::new().node(node).build()
         };
-        subscriber.receive_announcement(&create_link(channel_id, announce_id)?).await?;
-        match subscriber.fetch_next_msgs().await.pop(){
-            None => return Err(anyhow::Error::msg("There is no state in the channels")),
-            Some(m) => {
-                match m.body{
-                    MessageContent::SignedPacket { public_payload, masked_payload, .. } => {
-                        let comp = format!("{}:{}.state", channel_id, announce_id);
-                        let (public, masked): (String, Vec<u8>) = RawPacket::from_streams_response(&public_payload.0, &masked_payload.0, &None)?
-                            .deserialize()?;
-                        if public != comp{
-                            return Err(anyhow::Error::msg("There is no state in the channels"))
-                        }
-                        Ok(masked)
-                    }
-                    _ => return Err(anyhow::Error::msg("There is no state in the channels"))
+        let anchor = create_link(channel_id, announce_id)?;
+        subscriber.receive_announcement(&anchor).await?;
+        let author_pk = match subscriber.author_public_key(){
+            None => return Err(anyhow::Error::msg("The author of the channel is unknown")),
+            Some(pk) => pk.clone()
+        };
+
+        let comp = format!("{}:{}.state", channel_id, announce_id);
+        if let Some(last_index) = ChannelWriter::last_msg_index(&mut subscriber, &anchor).await{
+            for index in (0..=last_index).rev(){
+                if let Some(state) = ChannelWriter::checkpoint_at(&mut subscriber, &anchor, index, &author_pk, &comp).await{
+                    return Ok(state);
                 }
             }
         }
+
+        // The chunks of the packets of each branch are reassembled separately
+        let mut assemblers: HashMap<String, ChunkAssembler> = HashMap::new();
+        let mut last_state = None;
+        for msg in subscriber.fetch_all_next_msgs().await{
+            let msg_id = msg.link.msgid.to_string();
+            let (branch, p, m) = match ChannelWriter::author_payloads(msg.body, &author_pk){
+                None => continue,
+                Some(payloads) => payloads
+            };
+            let packets = assemblers.entry(branch.unwrap_or_default())
+                .or_insert_with(ChunkAssembler::new)
+                .push(&msg_id, &p, &m);
+            for (_, p, m) in packets.into_iter().flatten(){
+                if let Some(state) = ChannelWriter::checkpoint_state(&p, &m, &comp){
+                    last_state = Some(state);
+                }
+            }
+        }
+
+        match last_state{
+            None => Err(anyhow::Error::msg("There is no state in the channels")),
+            Some(state) => Ok(state)
+        }
+    }
+
+    ///
+    /// Find the index of the last msg of a single depth channel, doubling the index until a msg is missing
+    /// and then bisecting. It returns None if the first msg can't be fetched by index
+    ///
+    async fn last_msg_index(subscriber: &mut Subscriber<StreamsClient>, anchor: &Address) -> Option<u32>{
+        subscriber.receive_msg_by_sequence_number(anchor, 0).await.ok()?;
+        let (mut low, mut high) = (0u32, 1u32);
+        while subscriber.receive_msg_by_sequence_number(anchor, high).await.is_ok(){
+            low = high;
+            high = high.checked_mul(2)?;
+        }
+        while high - low > 1{
+            let mid = low + (high - low) / 2;
+            match subscriber.receive_msg_by_sequence_number(anchor, mid).await{
+                Ok(_) => low = mid,
+                Err(_) => high = mid
+            }
+        }
+        Some(low)
+    }
+
+    ///
+    /// Get the encrypted state of the checkpoint with the specified index of a single depth channel.
+    /// A chunked checkpoint is reassembled from its chunks, that have the next indexes
+    ///
+    async fn checkpoint_at(subscriber: &mut Subscriber<StreamsClient>, anchor: &Address, index: u32, author_pk: &PublicKey, comp: &str) -> Option<Vec<u8>>{
+        let (msg_id, p, m) = ChannelWriter::author_msg_at(subscriber, anchor, index, author_pk).await?;
+        let chunks = match decode_header(&p).ok()?{
+            None => return ChannelWriter::checkpoint_state(&p, &m, comp),
+            Some((ChunkHeader::Manifest{ chunks, .. }, _)) => chunks,
+            Some((ChunkHeader::Chunk{ .. }, _)) => return None
+        };
+        let mut assembler = ChunkAssembler::new();
+        assembler.push(&msg_id, &p, &m);
+        for offset in 1..=chunks{
+            let (chunk_id, p, m) = ChannelWriter::author_msg_at(subscriber, anchor, index.checked_add(offset)?, author_pk).await?;
+            if let Some(packet) = assembler.push(&chunk_id, &p, &m).pop(){
+                let (_, p, m) = packet.ok()?;
+                return ChannelWriter::checkpoint_state(&p, &m, comp);
+            }
+        }
+        None
+    }
+
+    async fn author_msg_at(subscriber: &mut Subscriber<StreamsClient>, anchor: &Address, index: u32, author_pk: &PublicKey) -> Option<(String, Vec<u8>, Vec<u8>)>{
+        let msg = subscriber.receive_msg_by_sequence_number(anchor, index).await.ok()?;
+        let msg_id = msg.link.msgid.to_string();
+        let (_, p, m) = ChannelWriter::author_payloads(msg.body, author_pk)?;
+        Some((msg_id, p, m))
+    }
+
+    ///
+    /// Get the branch and the payloads of a msg, only if it is signed by the author of the channel
+    ///
+    fn author_payloads(body: MessageContent, author_pk: &PublicKey) -> Option<(Option<String>, Vec<u8>, Vec<u8>)>{
+        match body{
+            MessageContent::SignedPacket { pk, public_payload, masked_payload } if pk == *author_pk => {
+                let (branch, p) = untag_branch_payload(&public_payload.0);
+                Some((branch, p, masked_payload.0))
+            }
+            _ => None
+        }
+    }
+
+    ///
+    /// Get the encrypted state of a checkpoint packet
+    ///
+    fn checkpoint_state(public: &[u8], masked: &[u8], comp: &str) -> Option<Vec<u8>>{
+        match RawPacket::from_streams_response(public, masked, &None)
+            .and_then(|packet| packet.deserialize::<String, Vec<u8>>()){
+            Ok((public, masked)) if public == comp => Some(masked),
+            _ => None
+        }
     }
 }
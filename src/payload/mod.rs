@@ -1,2 +1,9 @@
 pub mod payload_types;
 pub mod payload_serializers;
+pub mod payload_chunks;
+pub mod payload_files;
+pub mod payload_compression;
+pub mod payload_schema;
+pub mod payload_recipients;
+pub mod payload_ratchet;
+pub mod payload_keystore;
@@ -0,0 +1,191 @@
+use std::cmp::min;
+
+use anyhow::Result;
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use crypto::hashes::{
+    Digest,
+    blake2b::Blake2b256
+};
+use iota_streams::core::prelude::hex;
+use serde::{Deserialize, Serialize};
+
+use crate::utility::iota_utility::random_seed;
+
+///
+/// Default maximum size in bytes of the payloads of a single msg. Bigger packets are split in chunks
+///
+pub const DEFAULT_MAX_PAYLOAD_SIZE: usize = 16 * 1024;
+
+///
+/// Header prepended to the public payload of the msgs of a chunked packet.
+/// The `!` delimiter never appears in url-safe base64, so chunk msgs can be told apart from the others
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ChunkHeader{
+    Manifest{ id: String, chunks: u32, hash: String },
+    Chunk{ id: String, index: u32 },
+}
+
+///
+/// Split the encoded payloads of a packet in a manifest msg followed by the chunk msgs.
+/// Payloads that fit in a single msg are returned unchanged. The public and the masked payloads
+/// share the size of each msg: the chunks carry the public bytes first and then the masked ones,
+/// so that each chunk msg, header included, is never bigger than max_size
+///
+/// # Return Value
+/// It returns a Vector of Tuple containing (public_bytes, masked_bytes) of each msg to send
+///
+pub fn split_payloads(public: &[u8], masked: &[u8], max_size: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>>{
+    if public.len() + masked.len() <= max_size{
+        return Ok(vec![(public.to_vec(), masked.to_vec())]);
+    }
+
+    let id = random_seed()[..16].to_string();
+    let header_size = encode_header(&ChunkHeader::Chunk{ id: id.clone(), index: 0 }, &[])?.len();
+    let budget = match max_size.checked_sub(header_size){
+        Some(budget) if budget > 0 => budget,
+        _ => return Err(anyhow::Error::msg(format!("The maximum payload size must be greater than {}", header_size)))
+    };
+
+    let total = public.len() + masked.len();
+    let chunks = (total + budget - 1) / budget;
+    let manifest = encode_header(
+        &ChunkHeader::Manifest{ id: id.clone(), chunks: chunks as u32, hash: payloads_hash(public, masked) },
+        &[]
+    )?;
+    if manifest.len() > max_size{
+        return Err(anyhow::Error::msg(format!("The maximum payload size must be at least {}", manifest.len())));
+    }
+
+    let mut msgs = vec![(manifest, vec![])];
+    for index in 0..chunks{
+        let start = index * budget;
+        let end = min(start + budget, total);
+        let p = &public[min(start, public.len())..min(end, public.len())];
+        let m = &masked[start.saturating_sub(public.len())..end.saturating_sub(public.len())];
+        let header = ChunkHeader::Chunk{ id: id.clone(), index: index as u32 };
+        msgs.push((encode_header(&header, p)?, m.to_vec()));
+    }
+    Ok(msgs)
+}
+
+///
+/// Split the public payload of a msg in its chunk header, if any, and the remaining bytes
+///
+pub fn decode_header(public: &[u8]) -> Result<Option<(ChunkHeader, Vec<u8>)>>{
+    if public.first() != Some(&b'!'){
+        return Ok(None);
+    }
+    let end = match public[1..].iter().position(|b| *b == b'!'){
+        None => return Ok(None),
+        Some(end) => end + 1
+    };
+    let header_bytes = decode_config(&public[1..end], URL_SAFE_NO_PAD)?;
+    let header: ChunkHeader = bincode::deserialize(&header_bytes)?;
+    Ok(Some((header, public[end + 1..].to_vec())))
+}
+
+fn encode_header(header: &ChunkHeader, payload: &[u8]) -> Result<Vec<u8>>{
+    let header = encode_config(&bincode::serialize(header)?, URL_SAFE_NO_PAD);
+    Ok([format!("!{}!", header).as_bytes(), payload].concat())
+}
+
+fn payloads_hash(public: &[u8], masked: &[u8]) -> String{
+    let hash = Blake2b256::digest(&[public, masked].concat());
+    hex::encode(&hash)
+}
+
+struct PendingChunks{
+    msg_id: String,
+    id: String,
+    chunks: u32,
+    hash: String,
+    public: Vec<u8>,
+    masked: Vec<u8>,
+    received: u32,
+}
+
+///
+/// Reassembles chunked packets from the msgs fetched in order
+///
+pub struct ChunkAssembler{
+    pending: Option<PendingChunks>,
+}
+
+impl ChunkAssembler{
+    pub fn new() -> ChunkAssembler{
+        ChunkAssembler{ pending: None }
+    }
+
+    ///
+    /// Process a fetched msg
+    ///
+    /// # Return Value
+    /// It returns the Tuples (msg_id, public_bytes, masked_bytes) of the packets completed by the msg, using the id of the
+    /// manifest msg for chunked ones. The chunk sets left incomplete or corrupt are returned as errors, in order
+    ///
+    pub fn push(&mut self, msg_id: &str, public: &[u8], masked: &[u8]) -> Vec<Result<(String, Vec<u8>, Vec<u8>)>>{
+        let mut res = vec![];
+        let (header, p) = match decode_header(public){
+            Ok(Some(header)) => header,
+            Ok(None) => {
+                if let Some(pending) = self.pending.take(){
+                    res.push(Err(incomplete_error(&pending.msg_id)));
+                }
+                res.push(Ok((msg_id.to_string(), public.to_vec(), masked.to_vec())));
+                return res;
+            }
+            Err(_) => {
+                self.pending = None;
+                res.push(Err(anyhow::Error::msg(format!("Msg {} has a corrupt chunk header", msg_id))));
+                return res;
+            }
+        };
+
+        match header{
+            ChunkHeader::Manifest{ id, chunks, hash } => {
+                let previous = self.pending.replace(PendingChunks{
+                    msg_id: msg_id.to_string(),
+                    id,
+                    chunks,
+                    hash,
+                    public: vec![],
+                    masked: vec![],
+                    received: 0
+                });
+                if let Some(pending) = previous{
+                    res.push(Err(incomplete_error(&pending.msg_id)));
+                }
+            }
+            ChunkHeader::Chunk{ id, index } => {
+                let expected = matches!(&self.pending, Some(pending) if pending.id == id && pending.received == index);
+                if !expected{
+                    if let Some(pending) = self.pending.take(){
+                        res.push(Err(incomplete_error(&pending.msg_id)));
+                    }
+                    res.push(Err(anyhow::Error::msg(format!("Msg {} is a chunk of an incomplete set", msg_id))));
+                    return res;
+                }
+
+                let pending = self.pending.as_mut().unwrap();
+                pending.public.extend_from_slice(&p);
+                pending.masked.extend_from_slice(masked);
+                pending.received += 1;
+                if pending.received < pending.chunks{
+                    return res;
+                }
+
+                let pending = self.pending.take().unwrap();
+                match payloads_hash(&pending.public, &pending.masked) == pending.hash{
+                    true => res.push(Ok((pending.msg_id, pending.public, pending.masked))),
+                    false => res.push(Err(anyhow::Error::msg(format!("Chunked msg {} is corrupt", pending.msg_id))))
+                }
+            }
+        }
+        res
+    }
+}
+
+fn incomplete_error(msg_id: &str) -> anyhow::Error{
+    anyhow::Error::msg(format!("Chunked msg {} is incomplete", msg_id))
+}
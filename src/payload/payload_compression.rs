@@ -0,0 +1,36 @@
+use anyhow::Result;
+#[cfg(feature = "compression")]
+use std::io::{Read, Write};
+#[cfg(feature = "compression")]
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
+
+///
+/// Prefix of the compressed payloads. It never appears in url-safe base64,
+/// so compressed payloads can be told apart from the plain ones
+///
+pub const COMPRESSED_PREFIX: u8 = b'~';
+
+#[cfg(feature = "compression")]
+pub fn compress(data: &[u8]) -> Result<Vec<u8>>{
+    let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(feature = "compression")]
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>>{
+    let mut decoder = DeflateDecoder::new(data);
+    let mut res = vec![];
+    decoder.read_to_end(&mut res)?;
+    Ok(res)
+}
+
+#[cfg(not(feature = "compression"))]
+pub fn compress(_data: &[u8]) -> Result<Vec<u8>>{
+    Err(anyhow::Error::msg("Payload compression requires the compression feature"))
+}
+
+#[cfg(not(feature = "compression"))]
+pub fn decompress(_data: &[u8]) -> Result<Vec<u8>>{
+    Err(anyhow::Error::msg("Compressed payloads require the compression feature"))
+}
@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::Read;
+
+use anyhow::Result;
+use crypto::hashes::{
+    Digest,
+    blake2b::Blake2b256
+};
+use iota_streams::core::prelude::hex;
+use serde::{Deserialize, Serialize};
+
+use crate::utility::iota_utility::random_seed;
+
+///
+/// Default size in bytes of the data carried by each file chunk msg. The writer reduces it, before the
+/// manifest is sent, so that each chunk msg fits in its maximum payload size once encoded
+///
+pub const DEFAULT_FILE_CHUNK_SIZE: usize = 8 * 1024;
+
+///
+/// Msg of a file transfer. The manifest is sent first and describes the whole file
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum FilePart{
+    Manifest{ transfer_id: String, name: String, size: u64, chunks: u32, hash: String, metadata: HashMap<String, String> },
+    Chunk{ transfer_id: String, index: u32, data: Vec<u8> },
+}
+
+///
+/// Upload of a file in a channel. It keeps track of the confirmed chunks, so an interrupted
+/// upload can be resumed by sending it again. It can be serialized to survive restarts
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileTransfer{
+    transfer_id: String,
+    name: String,
+    metadata: HashMap<String, String>,
+    data: Vec<u8>,
+    chunk_size: usize,
+    next_chunk: u32,
+    manifest_msg_id: Option<String>,
+}
+
+impl FileTransfer{
+    pub fn new(name: &str, data: &[u8], metadata: HashMap<String, String>) -> FileTransfer{
+        FileTransfer{
+            transfer_id: random_seed()[..16].to_string(),
+            name: name.to_string(),
+            metadata,
+            data: data.to_vec(),
+            chunk_size: DEFAULT_FILE_CHUNK_SIZE,
+            next_chunk: 0,
+            manifest_msg_id: None,
+        }
+    }
+
+    ///
+    /// Create the transfer of the content read from any source, e.g. a file, a socket or an in memory buffer
+    ///
+    pub fn from_reader<R: Read>(name: &str, mut reader: R, metadata: HashMap<String, String>) -> Result<FileTransfer>{
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        Ok(FileTransfer::new(name, &data, metadata))
+    }
+
+    pub fn from_file(file_path: &str, metadata: HashMap<String, String>) -> Result<FileTransfer>{
+        let fr = OpenOptions::new().read(true).open(file_path)?;
+        let name = std::path::Path::new(file_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        FileTransfer::from_reader(&name, fr, metadata)
+    }
+
+    ///
+    /// Restore a transfer serialized with bincode, rejecting the ones that can't be split in chunks
+    ///
+    pub fn from_bytes(bytes: &[u8]) -> Result<FileTransfer>{
+        let transfer: FileTransfer = bincode::deserialize(bytes)?;
+        if transfer.chunk_size == 0{
+            return Err(anyhow::Error::msg("The chunk size of the transfer must be at least 1"));
+        }
+        Ok(transfer)
+    }
+
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self{
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    ///
+    /// Reduce the chunk size to the specified maximum. The chunk size can't change once the manifest is sent
+    ///
+    pub fn fit_chunk_size(&mut self, max_chunk_size: usize){
+        if self.manifest_msg_id.is_none(){
+            self.chunk_size = self.chunk_size.min(max_chunk_size).max(1);
+        }
+    }
+}
+
+impl FileTransfer{
+    pub fn transfer_id(&self) -> String {
+        self.transfer_id.clone()
+    }
+    pub fn chunks(&self) -> u32 {
+        let rem = (self.data.len() % self.chunk_size != 0) as usize;
+        (self.data.len() / self.chunk_size + rem) as u32
+    }
+    pub fn sent_chunks(&self) -> u32 {
+        self.next_chunk
+    }
+    pub fn is_completed(&self) -> bool {
+        self.manifest_msg_id.is_some() && self.next_chunk >= self.chunks()
+    }
+    pub fn manifest_msg_id(&self) -> Option<String> {
+        self.manifest_msg_id.clone()
+    }
+
+    pub fn manifest(&self) -> FilePart{
+        FilePart::Manifest{
+            transfer_id: self.transfer_id.clone(),
+            name: self.name.clone(),
+            size: self.data.len() as u64,
+            chunks: self.chunks(),
+            hash: file_hash(&self.data),
+            metadata: self.metadata.clone()
+        }
+    }
+
+    ///
+    /// Get the first chunk that has not been confirmed yet
+    ///
+    pub fn next_chunk(&self) -> Option<FilePart>{
+        let start = (self.next_chunk as usize).checked_mul(self.chunk_size)?;
+        if start >= self.data.len(){
+            return None;
+        }
+        let end = start.saturating_add(self.chunk_size).min(self.data.len());
+        Some(FilePart::Chunk{
+            transfer_id: self.transfer_id.clone(),
+            index: self.next_chunk,
+            data: self.data[start..end].to_vec()
+        })
+    }
+
+    pub fn confirm_manifest(&mut self, msg_id: &str){
+        self.manifest_msg_id = Some(msg_id.to_string());
+    }
+
+    pub fn confirm_chunk(&mut self){
+        self.next_chunk += 1;
+    }
+}
+
+///
+/// File received from a channel, after the verification of its content hash
+///
+#[derive(Debug, Clone)]
+pub struct ReceivedFile{
+    transfer_id: String,
+    name: String,
+    metadata: HashMap<String, String>,
+    data: Vec<u8>,
+}
+
+impl ReceivedFile{
+    pub fn transfer_id(&self) -> String {
+        self.transfer_id.clone()
+    }
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn metadata(&self) -> HashMap<String, String> {
+        self.metadata.clone()
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+    pub fn write_to_file(&self, file_path: &str) -> Result<()>{
+        std::fs::write(file_path, &self.data)?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct PartialFile{
+    manifest: Option<(String, HashMap<String, String>, u64, u32, String)>,
+    chunks: BTreeMap<u32, Vec<u8>>,
+}
+
+///
+/// Collects the file msgs of a channel. Chunks sent again by a resumed upload are ignored
+///
+#[derive(Default)]
+pub struct FileAssembler{
+    files: HashMap<String, PartialFile>,
+}
+
+impl FileAssembler{
+    pub fn new() -> FileAssembler{
+        FileAssembler::default()
+    }
+
+    pub fn push(&mut self, part: FilePart){
+        match part{
+            FilePart::Manifest{ transfer_id, name, size, chunks, hash, metadata } => {
+                let file = self.files.entry(transfer_id).or_default();
+                file.manifest = Some((name, metadata, size, chunks, hash));
+            }
+            FilePart::Chunk{ transfer_id, index, data } => {
+                let file = self.files.entry(transfer_id).or_default();
+                file.chunks.entry(index).or_insert(data);
+            }
+        }
+    }
+
+    ///
+    /// Take a file whose chunks have all been received. Chunks with an index out of the range of the manifest are ignored
+    ///
+    /// # Return Value
+    /// It returns None if no file is complete, or an error if the content hash of the complete file does not match
+    ///
+    pub fn take_complete(&mut self) -> Option<Result<ReceivedFile>>{
+        let transfer_id = self.files.iter()
+            .find(|(_, file)| match &file.manifest{
+                Some((_, _, _, chunks, _)) => file.chunks.range(..*chunks).count() as u32 >= *chunks,
+                None => false
+            })
+            .map(|(id, _)| id.clone())?;
+
+        let file = self.files.remove(&transfer_id)?;
+        let (name, metadata, size, chunks, hash) = file.manifest?;
+        let data: Vec<u8> = file.chunks.into_iter()
+            .filter(|(index, _)| *index < chunks)
+            .flat_map(|(_, chunk)| chunk)
+            .collect();
+        if data.len() as u64 != size || file_hash(&data) != hash{
+            return Some(Err(anyhow::Error::msg(format!("File {} is corrupt", transfer_id))));
+        }
+        Some(Ok(ReceivedFile{ transfer_id, name, metadata, data }))
+    }
+}
+
+fn file_hash(data: &[u8]) -> String{
+    let hash = Blake2b256::digest(data);
+    hex::encode(&hash)
+}
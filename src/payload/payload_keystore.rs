@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+
+use anyhow::Result;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::utility::iota_utility::{decrypt_data_with_prefixed_nonce, encrypt_data_with_random_nonce, random_seed};
+
+///
+/// First byte of the masked payloads encrypted with a key of a KeyStore, followed by the key id
+///
+pub const SHREDDABLE_V1: u8 = 0x06;
+
+const KEY_ID_LEN: usize = 16;
+
+///
+/// Local store of the per msg keys. It is never published in the channel, so erasing the key
+/// of a msg makes its masked payload permanently unreadable
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct KeyStore{
+    keys: HashMap<String, [u8; 32]>,
+    msgs: HashMap<String, String>,
+}
+
+impl KeyStore{
+    pub fn new() -> KeyStore{
+        KeyStore::default()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<KeyStore>{
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>>{
+        Ok(bincode::serialize(self)?)
+    }
+
+    pub fn from_file(file_path: &str) -> Result<KeyStore>{
+        let mut fr = OpenOptions::new().read(true).open(file_path)?;
+        let mut input = vec![];
+        fr.read_to_end(&mut input)?;
+        KeyStore::from_bytes(&input)
+    }
+
+    pub fn write_to_file(&self, file_path: &str) -> Result<()>{
+        let mut fr = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(file_path)?;
+        fr.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+}
+
+///
+/// The keys are never printed, only their ids
+///
+impl fmt::Debug for KeyStore{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyStore")
+            .field("keys", &self.keys.keys().collect::<Vec<&String>>())
+            .field("msgs", &self.msgs)
+            .finish()
+    }
+}
+
+impl KeyStore{
+    ///
+    /// Generate and store a new random key
+    ///
+    /// # Return Value
+    /// It returns a Tuple containing (key_id, key)
+    ///
+    pub fn generate_key(&mut self) -> (String, [u8; 32]){
+        let key_id = random_seed()[..KEY_ID_LEN].to_string();
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill(&mut key[..]);
+        self.keys.insert(key_id.clone(), key);
+        (key_id, key)
+    }
+
+    pub fn key(&self, key_id: &str) -> Option<[u8; 32]>{
+        self.keys.get(key_id).copied()
+    }
+
+    pub fn insert_key(&mut self, key_id: &str, key: [u8; 32]){
+        self.keys.insert(key_id.to_string(), key);
+    }
+
+    ///
+    /// Associate the key to the msg encrypted with it, so it can be erased by msg id
+    ///
+    pub fn link_msg(&mut self, msg_id: &str, key_id: &str){
+        self.msgs.insert(msg_id.to_string(), key_id.to_string());
+    }
+
+    pub fn msg_key_id(&self, msg_id: &str) -> Option<String>{
+        self.msgs.get(msg_id).cloned()
+    }
+
+    ///
+    /// Erase a key. It returns false if the key was not in the store
+    ///
+    pub fn erase_key(&mut self, key_id: &str) -> bool{
+        self.msgs.retain(|_, k| k != key_id);
+        self.keys.remove(key_id).is_some()
+    }
+
+    ///
+    /// Erase the key of a msg, making its masked payload unreadable. It returns false if the key was not in the store
+    ///
+    pub fn erase_msg(&mut self, msg_id: &str) -> bool{
+        match self.msgs.remove(msg_id){
+            None => false,
+            Some(key_id) => self.keys.remove(&key_id).is_some()
+        }
+    }
+
+    pub fn key_ids(&self) -> Vec<String>{
+        self.keys.keys().cloned().collect()
+    }
+}
+
+///
+/// Encrypt the masked data with the key of the store with the specified id
+///
+pub fn shreddable_encrypt(data: &[u8], key_id: &str, key: &[u8; 32]) -> Result<Vec<u8>>{
+    if key_id.len() != KEY_ID_LEN{
+        return Err(anyhow::Error::msg(format!("Invalid key id {}", key_id)));
+    }
+    let enc = encrypt_data_with_random_nonce(data, key)?;
+    Ok([&[SHREDDABLE_V1][..], key_id.as_bytes(), &enc].concat())
+}
+
+///
+/// Get the id of the key of the masked data, if it has been encrypted with a key of a KeyStore
+///
+pub fn shreddable_key_id(data: &[u8]) -> Option<String>{
+    match data.first(){
+        Some(&SHREDDABLE_V1) if data.len() > KEY_ID_LEN + 1 => String::from_utf8(data[1..KEY_ID_LEN + 1].to_vec()).ok(),
+        _ => None
+    }
+}
+
+pub fn shreddable_decrypt(data: &[u8], key_store: &KeyStore) -> Result<Vec<u8>>{
+    let key_id = match shreddable_key_id(data){
+        None => return Err(anyhow::Error::msg("The data has not been encrypted with a key of a key store")),
+        Some(key_id) => key_id
+    };
+    match key_store.key(&key_id){
+        None => Err(anyhow::Error::msg(format!("Key {} is not in the store or has been erased", key_id))),
+        Some(key) => decrypt_data_with_prefixed_nonce(&data[KEY_ID_LEN + 1..], &key)
+    }
+}
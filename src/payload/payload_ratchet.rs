@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+use anyhow::Result;
+use crypto::hashes::{
+    Digest,
+    blake2b::Blake2b256
+};
+use iota_streams::core::prelude::hex;
+use serde::{Deserialize, Serialize};
+
+use crate::utility::iota_utility::{decrypt_data_with_prefixed_nonce, encrypt_data_with_random_nonce};
+
+///
+/// First byte of the masked payloads encrypted with a ratchet key, followed by the ratchet position as big endian u64
+///
+pub const RATCHET_V1: u8 = 0x05;
+
+///
+/// Maximum number of steps walked to derive the key of a msg, so a forged position can't stall the reader
+///
+pub const MAX_RATCHET_SKIP: u64 = 1 << 14;
+
+///
+/// Maximum number of keys kept for the skipped positions, so the msgs received out of order can still be read
+///
+pub const MAX_SKIPPED_KEYS: usize = 1000;
+
+///
+/// Hash ratchet that derives a new key for the masked data of each msg. The chain key is replaced
+/// at every step, so a leaked ratchet exposes only the msgs from its position onward, and the keys
+/// of the skipped positions that have not been used yet
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyRatchet{
+    chain_key: [u8; 32],
+    position: u64,
+    skipped: BTreeMap<u64, [u8; 32]>,
+}
+
+impl KeyRatchet{
+    ///
+    /// Create the ratchet at position 0 from the channel secret
+    ///
+    pub fn new(channel_secret: &str) -> KeyRatchet{
+        KeyRatchet{
+            chain_key: derive(channel_secret.as_bytes(), b"ratchet"),
+            position: 0,
+            skipped: BTreeMap::new()
+        }
+    }
+
+    pub fn from_chain_key(chain_key: [u8; 32], position: u64) -> KeyRatchet{
+        KeyRatchet{ chain_key, position, skipped: BTreeMap::new() }
+    }
+
+    ///
+    /// Decode a ratchet exported with to_hex
+    ///
+    pub fn from_hex(ratchet: &str) -> Result<KeyRatchet>{
+        let bytes = hex::decode(ratchet)?;
+        if bytes.len() != 40{
+            return Err(anyhow::Error::msg("Invalid ratchet"));
+        }
+        let position = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        let chain_key = bytes[8..].try_into().unwrap();
+        Ok(KeyRatchet::from_chain_key(chain_key, position))
+    }
+
+    ///
+    /// Export the ratchet at its current position, to hand it to the readers that must read the msgs from here onward
+    ///
+    pub fn to_hex(&self) -> String{
+        hex::encode([&self.position.to_be_bytes()[..], &self.chain_key].concat())
+    }
+
+    pub fn position(&self) -> u64{
+        self.position
+    }
+
+    ///
+    /// Get the key of the current position and move the ratchet one step forward
+    ///
+    pub fn next_key(&mut self) -> (u64, [u8; 32]){
+        let res = (self.position, derive(&self.chain_key, b"msg"));
+        self.chain_key = derive(&self.chain_key, b"chain");
+        self.position += 1;
+        res
+    }
+
+    ///
+    /// Take the key of the specified position, moving the ratchet past it. The keys of the positions skipped
+    /// on the way are kept, only for the last MAX_SKIPPED_KEYS positions, and each key can be taken only once
+    ///
+    pub fn take_key(&mut self, position: u64) -> Result<[u8; 32]>{
+        if position < self.position{
+            return match self.skipped.remove(&position){
+                Some(key) => Ok(key),
+                None => Err(anyhow::Error::msg(format!("Ratchet position {} is no longer available", position)))
+            };
+        }
+        if position - self.position > MAX_RATCHET_SKIP{
+            return Err(anyhow::Error::msg(format!("Ratchet position {} is too far ahead", position)));
+        }
+        // The keys older than the kept window are derived only to move the chain forward
+        let first_kept = position.saturating_sub(MAX_SKIPPED_KEYS as u64);
+        while self.position < position{
+            let (skipped_position, key) = self.next_key();
+            if skipped_position >= first_kept{
+                self.skipped.insert(skipped_position, key);
+            }
+            if self.skipped.len() > MAX_SKIPPED_KEYS{
+                let oldest = *self.skipped.keys().next().unwrap();
+                self.skipped.remove(&oldest);
+            }
+        }
+        Ok(self.next_key().1)
+    }
+
+    ///
+    /// Move the ratchet forward to the specified position, forgetting the keys of the previous ones
+    ///
+    pub fn advance_to(&mut self, position: u64) -> Result<()>{
+        if position < self.position{
+            return Err(anyhow::Error::msg(format!("Ratchet position {} is no longer available", position)));
+        }
+        while self.position < position{
+            self.next_key();
+        }
+        self.skipped.clear();
+        Ok(())
+    }
+}
+
+///
+/// Encrypt the masked data with the key of the specified ratchet position
+///
+pub fn ratchet_encrypt(data: &[u8], position: u64, key: &[u8; 32]) -> Result<Vec<u8>>{
+    let enc = encrypt_data_with_random_nonce(data, key)?;
+    Ok([&[RATCHET_V1][..], &position.to_be_bytes(), &enc].concat())
+}
+
+///
+/// Get the ratchet position of the masked data, if it has been encrypted with a ratchet key
+///
+pub fn ratchet_position(data: &[u8]) -> Option<u64>{
+    match data.first(){
+        Some(&RATCHET_V1) if data.len() > 9 => Some(u64::from_be_bytes(data[1..9].try_into().unwrap())),
+        _ => None
+    }
+}
+
+///
+/// Decrypt the masked data with the key of its ratchet position. The ratchet is updated only if the data can be decrypted,
+/// so a forged msg can't move it forward
+///
+pub fn ratchet_decrypt(data: &[u8], ratchet: &mut KeyRatchet) -> Result<Vec<u8>>{
+    let position = match ratchet_position(data){
+        None => return Err(anyhow::Error::msg("The data has not been encrypted with a ratchet key")),
+        Some(position) => position
+    };
+    let mut next = ratchet.clone();
+    let dec = decrypt_data_with_prefixed_nonce(&data[9..], &next.take_key(position)?)?;
+    *ratchet = next;
+    Ok(dec)
+}
+
+fn derive(key: &[u8], label: &[u8]) -> [u8; 32]{
+    let hash = Blake2b256::digest(&[key, label].concat());
+    let mut res = [0u8; 32];
+    res.copy_from_slice(&hash);
+    res
+}
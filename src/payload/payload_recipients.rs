@@ -0,0 +1,165 @@
+use std::convert::TryInto;
+
+use anyhow::Result;
+use crypto::hashes::{
+    Digest,
+    blake2b::Blake2b256
+};
+use iota_streams::core::prelude::hex;
+use rand::Rng;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use x25519_dalek::{EphemeralSecret, PublicKey, SharedSecret, StaticSecret};
+
+use crate::utility::iota_utility::{decrypt_data_with_prefixed_nonce, encrypt_data_with_random_nonce};
+
+///
+/// First byte of the masked payloads encrypted for a list of recipients
+///
+pub const RECIPIENTS_V1: u8 = 0x04;
+
+///
+/// X25519 key pair of a recipient of the masked payloads
+///
+#[derive(Clone)]
+pub struct RecipientKey{
+    secret: StaticSecret,
+}
+
+impl RecipientKey{
+    pub fn generate() -> RecipientKey{
+        RecipientKey::from_bytes(random_key())
+    }
+
+    pub fn from_bytes(secret: [u8; 32]) -> RecipientKey{
+        RecipientKey{ secret: StaticSecret::from(secret) }
+    }
+
+    pub fn from_hex(secret: &str) -> Result<RecipientKey>{
+        let bytes = hex::decode(secret)?;
+        match bytes.as_slice().try_into(){
+            Ok(secret) => Ok(RecipientKey::from_bytes(secret)),
+            Err(_) => Err(anyhow::Error::msg("Invalid recipient secret key"))
+        }
+    }
+
+    pub fn to_hex(&self) -> String{
+        hex::encode(self.secret.to_bytes())
+    }
+
+    pub fn public_key(&self) -> [u8; 32]{
+        PublicKey::from(&self.secret).to_bytes()
+    }
+
+    pub fn public_key_hex(&self) -> String{
+        hex::encode(self.public_key())
+    }
+}
+
+///
+/// Decode an hex encoded recipient public key
+///
+pub fn recipient_from_hex(public_key: &str) -> Result<[u8; 32]>{
+    let bytes = hex::decode(public_key)?;
+    match bytes.as_slice().try_into(){
+        Ok(pk) => Ok(pk),
+        Err(_) => Err(anyhow::Error::msg(format!("Invalid recipient public key {}", public_key)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct WrappedKey{
+    recipient: [u8; 32],
+    ephemeral_pk: [u8; 32],
+    key: Vec<u8>,
+}
+
+///
+/// Masked data encrypted once with a random content key, wrapped for each recipient
+/// with a key agreed between a fresh ephemeral key and the recipient public key
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecipientEnvelope{
+    keys: Vec<WrappedKey>,
+    data: Vec<u8>,
+}
+
+impl RecipientEnvelope{
+    pub fn seal(data: &[u8], recipients: &[[u8; 32]]) -> Result<RecipientEnvelope>{
+        let content_key = random_key();
+
+        let mut keys = Vec::with_capacity(recipients.len());
+        for recipient in recipients{
+            let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+            let ephemeral_pk = PublicKey::from(&ephemeral).to_bytes();
+            let shared = checked_shared_secret(ephemeral.diffie_hellman(&PublicKey::from(*recipient)))?;
+            let wrap_key = wrapping_key(&shared, &ephemeral_pk, recipient);
+            keys.push(WrappedKey{
+                recipient: *recipient,
+                ephemeral_pk,
+                key: encrypt_data_with_random_nonce(&content_key, &wrap_key)?
+            });
+        }
+
+        Ok(RecipientEnvelope{
+            keys,
+            data: encrypt_data_with_random_nonce(data, &content_key)?
+        })
+    }
+
+    pub fn open(&self, recipient: &RecipientKey) -> Result<Vec<u8>>{
+        let pk = recipient.public_key();
+        let wrapped = match self.keys.iter().find(|k| k.recipient == pk){
+            None => return Err(anyhow::Error::msg("The msg has not been encrypted for this recipient")),
+            Some(wrapped) => wrapped
+        };
+        let shared = checked_shared_secret(recipient.secret.diffie_hellman(&PublicKey::from(wrapped.ephemeral_pk)))?;
+        let wrap_key = wrapping_key(&shared, &wrapped.ephemeral_pk, &pk);
+        let content_key: [u8; 32] = match decrypt_data_with_prefixed_nonce(&wrapped.key, &wrap_key)?.as_slice().try_into(){
+            Ok(key) => key,
+            Err(_) => return Err(anyhow::Error::msg("Invalid content key"))
+        };
+        decrypt_data_with_prefixed_nonce(&self.data, &content_key)
+    }
+
+    pub fn recipients(&self) -> Vec<[u8; 32]>{
+        self.keys.iter().map(|k| k.recipient).collect()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>>{
+        Ok([&[RECIPIENTS_V1][..], &bincode::serialize(self)?].concat())
+    }
+
+    ///
+    /// Decode the envelope of a masked payload. It returns None if the payload is not encrypted for a list of recipients
+    ///
+    pub fn from_bytes(data: &[u8]) -> Option<RecipientEnvelope>{
+        match data.first(){
+            Some(&RECIPIENTS_V1) => bincode::deserialize(&data[1..]).ok(),
+            _ => None
+        }
+    }
+}
+
+fn random_key() -> [u8; 32]{
+    let mut key = [0u8; 32];
+    rand::thread_rng().fill(&mut key[..]);
+    key
+}
+
+///
+/// Reject the all-zero shared secret agreed with a low order public key, which would make the wrapping key predictable
+///
+fn checked_shared_secret(shared: SharedSecret) -> Result<[u8; 32]>{
+    match shared.as_bytes() == &[0u8; 32]{
+        true => Err(anyhow::Error::msg("Invalid recipient public key")),
+        false => Ok(*shared.as_bytes())
+    }
+}
+
+fn wrapping_key(shared: &[u8; 32], ephemeral_pk: &[u8; 32], recipient: &[u8; 32]) -> [u8; 32]{
+    let hash = Blake2b256::digest(&[&shared[..], &ephemeral_pk[..], &recipient[..]].concat());
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&hash);
+    key
+}
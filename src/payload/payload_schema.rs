@@ -0,0 +1,136 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Result;
+use jsonschema::JSONSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::payload::payload_types::{StreamsPacket, StreamsPacketSerializer};
+
+///
+/// JSON Schemas of the public and masked payloads of the json packets of a channel.
+/// The schemas are kept as json text, so they can be stored in the channel state and announced in the channel,
+/// and they are compiled once when the PacketSchema is created
+///
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "SchemaText", into = "SchemaText")]
+pub struct PacketSchema{
+    public: Option<CompiledSchema>,
+    masked: Option<CompiledSchema>,
+}
+
+///
+/// Layout of the PacketSchema in the channel state and in the schema msgs
+///
+#[derive(Serialize, Deserialize)]
+struct SchemaText{
+    public: Option<String>,
+    masked: Option<String>,
+}
+
+#[derive(Clone)]
+struct CompiledSchema{
+    text: String,
+    schema: Arc<JSONSchema>,
+}
+
+impl PacketSchema{
+    ///
+    /// Create the schema from the json text of the public and masked schemas. A missing schema accepts any payload
+    ///
+    pub fn new(public: Option<&str>, masked: Option<&str>) -> Result<PacketSchema>{
+        Ok(PacketSchema{
+            public: public.map(compile).transpose()?,
+            masked: masked.map(compile).transpose()?
+        })
+    }
+
+    pub fn public(&self) -> Option<String> {
+        self.public.as_ref().map(|schema| schema.text.clone())
+    }
+    pub fn masked(&self) -> Option<String> {
+        self.masked.as_ref().map(|schema| schema.text.clone())
+    }
+
+    pub fn validate_public(&self, data: &Value) -> Result<()>{
+        match &self.public{
+            None => Ok(()),
+            Some(schema) => validate(schema, data)
+        }
+    }
+
+    pub fn validate_masked(&self, data: &Value) -> Result<()>{
+        match &self.masked{
+            None => Ok(()),
+            Some(schema) => validate(schema, data)
+        }
+    }
+
+    ///
+    /// Validate both the payloads of a packet. They must be deserializable as json values
+    ///
+    pub fn validate_packet<P>(&self, packet: &StreamsPacket<P>) -> Result<()>
+    where
+        P: StreamsPacketSerializer
+    {
+        if self.public.is_some(){
+            self.validate_public(&packet.deserialize_public_auto()?)?;
+        }
+        if self.masked.is_some(){
+            self.validate_masked(&packet.deserialize_masked_auto()?)?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for PacketSchema{
+    fn eq(&self, other: &Self) -> bool {
+        self.public() == other.public() && self.masked() == other.masked()
+    }
+}
+
+impl fmt::Debug for PacketSchema{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PacketSchema")
+            .field("public", &self.public())
+            .field("masked", &self.masked())
+            .finish()
+    }
+}
+
+impl TryFrom<SchemaText> for PacketSchema{
+    type Error = anyhow::Error;
+
+    fn try_from(text: SchemaText) -> Result<Self> {
+        PacketSchema::new(text.public.as_deref(), text.masked.as_deref())
+    }
+}
+
+impl From<PacketSchema> for SchemaText{
+    fn from(schema: PacketSchema) -> Self {
+        SchemaText{
+            public: schema.public(),
+            masked: schema.masked()
+        }
+    }
+}
+
+fn compile(text: &str) -> Result<CompiledSchema>{
+    let value: Value = serde_json::from_str(text)?;
+    match JSONSchema::compile(&value){
+        Ok(schema) => Ok(CompiledSchema{ text: text.to_string(), schema: Arc::new(schema) }),
+        Err(e) => Err(anyhow::Error::msg(format!("Invalid JSON schema: {}", e)))
+    }
+}
+
+fn validate(schema: &CompiledSchema, data: &Value) -> Result<()>{
+    let res = schema.schema.validate(data).map_err(|errors| {
+        errors.map(|e| e.to_string()).collect::<Vec<String>>().join(", ")
+    });
+    match res{
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow::Error::msg(format!("Payload does not conform to the schema: {}", e)))
+    }
+}
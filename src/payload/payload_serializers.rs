@@ -8,35 +8,114 @@ use crate::payload::payload_types::{StreamsPacket, StreamsPacketBuilder, Streams
 pub struct RawSerializer;
 
 impl StreamsPacketSerializer for RawSerializer{
+    const SERIALIZER_ID: u8 = 1;
+
     fn serialize<T: Serialize>(data: &T) -> Result<String> {
-        let bytes = bincode::serialize(data)?;
-        Ok(hex::encode(bytes))
+        Ok(hex::encode(Self::serialize_bytes(data)?))
     }
 
     fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
-        let data = hex::decode(data)?;
-        Ok(bincode::deserialize(&data)?)
+        Self::deserialize_bytes(&hex::decode(data)?)
+    }
+
+    fn serialize_bytes<T: Serialize>(data: &T) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(data)?)
+    }
+
+    fn deserialize_bytes<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+        Ok(bincode::deserialize(data)?)
     }
 }
 
 pub struct JsonSerializer;
 
 impl StreamsPacketSerializer for JsonSerializer{
+    const SERIALIZER_ID: u8 = 2;
+
     fn serialize<T: Serialize>(data: &T) -> Result<String> {
-        let bytes = serde_json::to_string(data)?.as_bytes().to_vec();
-        let bytes = bincode::serialize(&bytes)?;
-        Ok(hex::encode(bytes))
+        Ok(hex::encode(Self::serialize_bytes(data)?))
     }
 
     fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
-        let data = hex::decode(data)?;
-        let data: Vec<u8> = bincode::deserialize(&data)?;
+        Self::deserialize_bytes(&hex::decode(data)?)
+    }
+
+    fn serialize_bytes<T: Serialize>(data: &T) -> Result<Vec<u8>> {
+        let bytes = serde_json::to_string(data)?.as_bytes().to_vec();
+        Ok(bincode::serialize(&bytes)?)
+    }
+
+    fn deserialize_bytes<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+        let data: Vec<u8> = bincode::deserialize(data)?;
         Ok(serde_json::from_slice(&data)?)
     }
 }
 
+pub struct CborSerializer;
+
+impl StreamsPacketSerializer for CborSerializer{
+    const SERIALIZER_ID: u8 = 3;
+
+    fn serialize<T: Serialize>(data: &T) -> Result<String> {
+        Ok(hex::encode(Self::serialize_bytes(data)?))
+    }
+
+    fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+        Self::deserialize_bytes(&hex::decode(data)?)
+    }
+
+    fn serialize_bytes<T: Serialize>(data: &T) -> Result<Vec<u8>> {
+        Ok(serde_cbor::to_vec(data)?)
+    }
+
+    fn deserialize_bytes<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+        Ok(serde_cbor::from_slice(data)?)
+    }
+}
+
+pub struct MsgPackSerializer;
+
+impl StreamsPacketSerializer for MsgPackSerializer{
+    const SERIALIZER_ID: u8 = 4;
+
+    fn serialize<T: Serialize>(data: &T) -> Result<String> {
+        Ok(hex::encode(Self::serialize_bytes(data)?))
+    }
+
+    fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+        Self::deserialize_bytes(&hex::decode(data)?)
+    }
+
+    fn serialize_bytes<T: Serialize>(data: &T) -> Result<Vec<u8>> {
+        Ok(rmp_serde::to_vec_named(data)?)
+    }
+
+    fn deserialize_bytes<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+        Ok(rmp_serde::from_read_ref(data)?)
+    }
+}
+
+///
+/// Deserialize the data with the serializer that has the specified id
+///
+pub fn deserialize_by_id<T: DeserializeOwned>(serializer_id: u8, data: &[u8]) -> Result<T>{
+    match serializer_id{
+        RawSerializer::SERIALIZER_ID => RawSerializer::deserialize_bytes(data),
+        JsonSerializer::SERIALIZER_ID => JsonSerializer::deserialize_bytes(data),
+        CborSerializer::SERIALIZER_ID => CborSerializer::deserialize_bytes(data),
+        MsgPackSerializer::SERIALIZER_ID => MsgPackSerializer::deserialize_bytes(data),
+        _ => Err(anyhow::Error::msg(format!("Unknown serializer id {}", serializer_id)))
+    }
+}
+
 pub type RawPacket = StreamsPacket<RawSerializer>;
 pub type RawPacketBuilder = StreamsPacketBuilder<RawSerializer>;
 
 pub type JsonPacket = StreamsPacket<JsonSerializer>;
 pub type JsonPacketBuilder = StreamsPacketBuilder<JsonSerializer>;
+
+pub type CborPacket = StreamsPacket<CborSerializer>;
+pub type CborPacketBuilder = StreamsPacketBuilder<CborSerializer>;
+
+pub type MsgPackPacket = StreamsPacket<MsgPackSerializer>;
+pub type MsgPackPacketBuilder = StreamsPacketBuilder<MsgPackSerializer>;
@@ -1,15 +1,96 @@
 use std::marker::PhantomData;
+use std::convert::TryFrom;
 
 use anyhow::Result;
 use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use iota_streams::core::prelude::hex;
 use iota_streams::ddml::types::Bytes;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
-use crate::utility::iota_utility::{decrypt_data, encrypt_data};
+use serde::{Deserialize, Serialize};
+use crate::utility::iota_utility::{decrypt_data, decrypt_data_with_prefixed_nonce, encrypt_data_with_random_nonce};
+use crate::payload::payload_compression::{compress, decompress, COMPRESSED_PREFIX};
+use crate::payload::payload_serializers::deserialize_by_id;
+use crate::payload::payload_recipients::{RecipientEnvelope, RecipientKey};
+use crate::payload::payload_ratchet::{KeyRatchet, ratchet_decrypt, ratchet_encrypt, ratchet_position};
+use crate::payload::payload_keystore::{KeyStore, shreddable_decrypt, shreddable_encrypt, shreddable_key_id};
+
+///
+/// First byte of the payloads in the compact encoding. It never appears at the beginning of the
+/// legacy base64 payloads, so messages in both encodings can be read
+///
+pub const COMPACT_V1: u8 = 0x01;
+pub const COMPACT_V1_COMPRESSED: u8 = 0x02;
+
+///
+/// First byte of the public payloads that start with a PacketEnvelope, followed by its length as big endian u16
+///
+pub const ENVELOPE_V1: u8 = 0x03;
+
+///
+/// First byte of the masked payloads encrypted with the key of a KeyNonce and a random nonce, followed by the nonce
+///
+pub const KEY_NONCE_V1: u8 = 0x07;
 
 pub trait StreamsPacketSerializer {
+    ///
+    /// Identifier written in the packet envelope. 0 is reserved for custom serializers
+    ///
+    const SERIALIZER_ID: u8 = 0;
+
+    ///
+    /// Serialize the data in a hex encoded string
+    ///
     fn serialize<T: Serialize>(data: &T) -> Result<String>;
+
+    ///
+    /// Deserialize the data from its hex encoding
+    ///
     fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Result<T>;
+
+    ///
+    /// Serialize the data in raw bytes. The default implementation decodes the output of serialize,
+    /// the serializers can override it to skip the hex encoding
+    ///
+    fn serialize_bytes<T: Serialize>(data: &T) -> Result<Vec<u8>>{
+        Ok(hex::decode(Self::serialize(data)?)?)
+    }
+
+    ///
+    /// Deserialize the data from raw bytes. The default implementation hex encodes them for deserialize
+    ///
+    fn deserialize_bytes<T: DeserializeOwned>(data: &[u8]) -> Result<T>{
+        Self::deserialize(hex::encode(data).as_bytes())
+    }
+}
+
+///
+/// Header that describes the content of a packet, so that readers can choose the deserializer automatically
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PacketEnvelope{
+    content_type: String,
+    serializer_id: u8,
+    schema_version: u32,
+}
+
+impl PacketEnvelope{
+    pub fn new(content_type: &str, serializer_id: u8, schema_version: u32) -> PacketEnvelope{
+        PacketEnvelope{
+            content_type: content_type.to_string(),
+            serializer_id,
+            schema_version
+        }
+    }
+
+    pub fn content_type(&self) -> String {
+        self.content_type.clone()
+    }
+    pub fn serializer_id(&self) -> u8 {
+        self.serializer_id
+    }
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
 }
 
 pub struct StreamsPacket<P>{
@@ -17,50 +98,153 @@ pub struct StreamsPacket<P>{
     m_data: Vec<u8>,
     _marker: PhantomData<P>,
     key_nonce: Option<([u8;32], [u8;24])>,
+    compressed: bool,
+    compact: bool,
+    envelope: Option<PacketEnvelope>,
+    recipients: Vec<[u8;32]>,
+    ratchet_key: Option<(u64, [u8;32])>,
+    shreddable_key: Option<(String, [u8;32])>,
+    sealed: bool,
+}
+
+///
+/// Key used to decrypt the masked data of a msg
+///
+enum MaskedKey<'a>{
+    KeyNonce(&'a Option<([u8;32], [u8;24])>),
+    Recipient(&'a RecipientKey),
+    Ratchet(&'a mut KeyRatchet),
+    KeyStore(&'a KeyStore),
 }
 
 impl<P> StreamsPacket<P>
 where
     P: StreamsPacketSerializer,
 {
-    fn new(p_data: &[u8], m_data: &[u8], key_nonce: Option<([u8;32], [u8;24])>) -> StreamsPacket<P>{
-        StreamsPacket{
-            p_data: p_data.to_vec(),
-            m_data: m_data.to_vec(),
-            _marker: PhantomData,
-            key_nonce
-        }
+    ///
+    /// Decode the payloads of a msg. The masked data of the msgs encrypted for a list of recipients
+    /// or with a ratchet key is left empty, use the specific constructors to read it
+    ///
+    pub fn from_streams_response(p_data: &[u8], m_data: &[u8], key_nonce: &Option<([u8;32], [u8;24])>) -> Result<StreamsPacket<P>>{
+        StreamsPacket::decode(p_data, m_data, MaskedKey::KeyNonce(key_nonce))
     }
 
-    pub fn from_streams_response(p_data: &[u8], m_data: &[u8], key_nonce: &Option<([u8;32], [u8;24])>) -> Result<StreamsPacket<P>>{
-        let (p, m) = match key_nonce{
-            None => (p_data.to_vec(), m_data.to_vec()),
-            Some((key, nonce)) => {
-                let dec = decrypt_data(m_data, key, nonce)?;
-                (p_data.to_vec(), dec)
+    ///
+    /// Decode the payloads of a msg, decrypting the masked data with the key of one of its recipients
+    ///
+    pub fn from_streams_response_for_recipient(p_data: &[u8], m_data: &[u8], recipient: &RecipientKey) -> Result<StreamsPacket<P>>{
+        StreamsPacket::decode(p_data, m_data, MaskedKey::Recipient(recipient))
+    }
+
+    ///
+    /// Decode the payloads of a msg, decrypting the masked data with the key derived by the ratchet, that moves past
+    /// the position of the msg. It fails if the msg precedes the position of the ratchet and its key is not kept anymore
+    ///
+    pub fn from_streams_response_with_ratchet(p_data: &[u8], m_data: &[u8], ratchet: &mut KeyRatchet) -> Result<StreamsPacket<P>>{
+        StreamsPacket::decode(p_data, m_data, MaskedKey::Ratchet(ratchet))
+    }
+
+    ///
+    /// Decode the payloads of a msg, decrypting the masked data with its key in the store.
+    /// It fails if the key has been erased
+    ///
+    pub fn from_streams_response_with_key_store(p_data: &[u8], m_data: &[u8], key_store: &KeyStore) -> Result<StreamsPacket<P>>{
+        StreamsPacket::decode(p_data, m_data, MaskedKey::KeyStore(key_store))
+    }
+
+    fn decode(p_data: &[u8], m_data: &[u8], masked_key: MaskedKey) -> Result<StreamsPacket<P>>{
+        let recipient_envelope = RecipientEnvelope::from_bytes(m_data);
+        let is_ratcheted = ratchet_position(m_data).is_some();
+        let is_shreddable = shreddable_key_id(m_data).is_some();
+        let is_key_nonce = m_data.first() == Some(&KEY_NONCE_V1);
+        let mut key_nonce = None;
+        let m = match masked_key{
+            MaskedKey::Recipient(recipient) => match recipient_envelope{
+                None => Some(m_data.to_vec()),
+                Some(recipient_envelope) => Some(recipient_envelope.open(recipient)?)
+            },
+            MaskedKey::Ratchet(ratchet) => match is_ratcheted{
+                false => Some(m_data.to_vec()),
+                true => Some(ratchet_decrypt(m_data, ratchet)?)
+            },
+            MaskedKey::KeyStore(key_store) => match is_shreddable{
+                false => Some(m_data.to_vec()),
+                true => Some(shreddable_decrypt(m_data, key_store)?)
+            },
+            MaskedKey::KeyNonce(None) if recipient_envelope.is_some() || is_ratcheted || is_shreddable || is_key_nonce => None,
+            MaskedKey::KeyNonce(None) => Some(m_data.to_vec()),
+            MaskedKey::KeyNonce(Some((key, nonce))) => {
+                key_nonce = Some((*key, *nonce));
+                let tagged = match is_key_nonce{
+                    true => decrypt_data_with_prefixed_nonce(&m_data[1..], key).ok(),
+                    false => None
+                };
+                // Legacy msgs are encrypted with the fixed nonce of the KeyNonce, and can start with the tag by chance
+                let dec = match tagged{
+                    Some(dec) => dec,
+                    None => decrypt_data(m_data, key, nonce)?
+                };
+                Some(dec)
             }
         };
 
+        let (envelope, p) = split_envelope(p_data)?;
+        let (p_data, p_compressed, p_compact) = decode_payload(&p)?;
+        let (m_data, m_compressed, m_compact, sealed) = match m{
+            None => (vec![], false, false, true),
+            Some(m) => {
+                // The envelope is written only in the public payload
+                let (m_data, m_compressed, m_compact) = decode_payload(&m)?;
+                (m_data, m_compressed, m_compact, false)
+            }
+        };
         Ok(
             StreamsPacket{
-            p_data: decode_config(p, URL_SAFE_NO_PAD)?,
-            m_data: decode_config(m, URL_SAFE_NO_PAD)?,
+            p_data,
+            m_data,
             _marker: PhantomData,
-            key_nonce: key_nonce.clone(),
+            key_nonce,
+            compressed: p_compressed || m_compressed,
+            compact: p_compact || m_compact,
+            envelope,
+            recipients: vec![],
+            ratchet_key: None,
+            shreddable_key: None,
+            sealed,
             }
         )
     }
 
     pub fn public_data(&self) -> Result<Bytes> {
-        let p = encode_config(&self.p_data, URL_SAFE_NO_PAD).as_bytes().to_vec();
+        let p = encode_payload(&self.p_data, self.compressed, self.compact)?;
+        let p = match &self.envelope{
+            None => p,
+            Some(envelope) => {
+                let header = bincode::serialize(envelope)?;
+                let header_len = match u16::try_from(header.len()){
+                    Ok(len) => len,
+                    Err(_) => return Err(anyhow::Error::msg("The packet envelope is too big"))
+                };
+                [&[ENVELOPE_V1][..], &header_len.to_be_bytes(), &header, &p].concat()
+            }
+        };
         Ok(Bytes(p))
     }
 
     pub fn masked_data(&self) -> Result<Bytes> {
-        let m = encode_config(&self.m_data, URL_SAFE_NO_PAD).as_bytes().to_vec();
+        let m = encode_payload(&self.m_data, self.compressed, self.compact)?;
+        if !self.recipients.is_empty(){
+            return Ok(Bytes(RecipientEnvelope::seal(&m, &self.recipients)?.to_bytes()?));
+        }
+        if let Some((position, key)) = &self.ratchet_key{
+            return Ok(Bytes(ratchet_encrypt(&m, *position, key)?));
+        }
+        if let Some((key_id, key)) = &self.shreddable_key{
+            return Ok(Bytes(shreddable_encrypt(&m, key_id, key)?));
+        }
         let data = match &self.key_nonce{
             None => m,
-            Some((key, nonce)) => encrypt_data(&m, key, nonce)?
+            Some((key, _)) => [&[KEY_NONCE_V1][..], &encrypt_data_with_random_nonce(&m, key)?].concat()
         };
 
         Ok(Bytes(data))
@@ -71,30 +255,155 @@ where
         U: DeserializeOwned,
         T: DeserializeOwned,
     {
-        Ok((P::deserialize(&self.p_data)?, P::deserialize(&self.m_data)?))
+        Ok((P::deserialize_bytes(&self.p_data)?, P::deserialize_bytes(&self.m_data)?))
     }
 
     pub fn deserialize_public<T>(&self) -> Result<T>
     where
         T: DeserializeOwned
     {
-        Ok(P::deserialize(&self.p_data)?)
+        Ok(P::deserialize_bytes(&self.p_data)?)
     }
 
     pub fn deserialize_masked<T>(&self) -> Result<T>
         where
             T: DeserializeOwned
     {
-        Ok(P::deserialize(&self.m_data)?)
+        Ok(P::deserialize_bytes(&self.m_data)?)
+    }
+
+    pub fn is_compressed(&self) -> bool{
+        self.compressed
+    }
+
+    pub fn is_compact(&self) -> bool{
+        self.compact
+    }
+
+    pub fn envelope(&self) -> Option<PacketEnvelope>{
+        self.envelope.clone()
+    }
+
+    ///
+    /// Get the serialized public data, before its deserialization
+    ///
+    pub fn public_bytes(&self) -> &[u8]{
+        &self.p_data
     }
 
+    ///
+    /// Get the serialized masked data, before its deserialization
+    ///
+    pub fn masked_bytes(&self) -> &[u8]{
+        &self.m_data
+    }
+
+    ///
+    /// Get the id of the key in the store used to encrypt the masked data, if any
+    ///
+    pub fn shreddable_key_id(&self) -> Option<String>{
+        self.shreddable_key.as_ref().map(|(key_id, _)| key_id.clone())
+    }
+
+    ///
+    /// Check if the masked data has been encrypted for a list of recipients, with a ratchet key,
+    /// with a key of a store or with a KeyNonce, and has not been decrypted
+    ///
+    pub fn is_sealed(&self) -> bool{
+        self.sealed
+    }
+
+    ///
+    /// Deserialize the public data with the serializer declared in the envelope, or with the packet one if there is no envelope
+    ///
+    pub fn deserialize_public_auto<T>(&self) -> Result<T>
+    where
+        T: DeserializeOwned
+    {
+        match &self.envelope{
+            None => P::deserialize_bytes(&self.p_data),
+            Some(envelope) => deserialize_by_id(envelope.serializer_id(), &self.p_data)
+        }
+    }
+
+    ///
+    /// Deserialize the masked data with the serializer declared in the envelope, or with the packet one if there is no envelope
+    ///
+    pub fn deserialize_masked_auto<T>(&self) -> Result<T>
+    where
+        T: DeserializeOwned
+    {
+        match &self.envelope{
+            None => P::deserialize_bytes(&self.m_data),
+            Some(envelope) => deserialize_by_id(envelope.serializer_id(), &self.m_data)
+        }
+    }
+
+}
+
+///
+/// Encode the serialized bytes of a payload. The compact encoding puts them straight in the msg after the version
+/// marker, while the legacy one hex encodes them and then encodes the result in url-safe base64
+///
+fn encode_payload(data: &[u8], compressed: bool, compact: bool) -> Result<Vec<u8>>{
+    match (compact, compressed){
+        (true, false) => Ok([&[COMPACT_V1][..], data].concat()),
+        (true, true) => Ok([&[COMPACT_V1_COMPRESSED][..], &compress(data)?].concat()),
+        (false, false) => Ok(encode_config(hex::encode(data), URL_SAFE_NO_PAD).as_bytes().to_vec()),
+        (false, true) => {
+            let enc = encode_config(&compress(hex::encode(data).as_bytes())?, URL_SAFE_NO_PAD);
+            Ok([&[COMPRESSED_PREFIX][..], enc.as_bytes()].concat())
+        }
+    }
+}
+
+///
+/// Split a public payload in its envelope, if any, and the encoded data
+///
+fn split_envelope(data: &[u8]) -> Result<(Option<PacketEnvelope>, Vec<u8>)>{
+    if data.first() != Some(&ENVELOPE_V1){
+        return Ok((None, data.to_vec()));
+    }
+    if data.len() < 3{
+        return Err(anyhow::Error::msg("Invalid packet envelope"));
+    }
+    let len = u16::from_be_bytes([data[1], data[2]]) as usize;
+    if data.len() < 3 + len{
+        return Err(anyhow::Error::msg("Invalid packet envelope"));
+    }
+    let envelope: PacketEnvelope = bincode::deserialize(&data[3..3 + len])?;
+    Ok((Some(envelope), data[3 + len..].to_vec()))
+}
+
+///
+/// Decode a payload in any of the encodings, decompressing it if needed
+///
+/// # Return Value
+/// It returns a Tuple containing (serialized_bytes, compressed, compact)
+///
+fn decode_payload(data: &[u8]) -> Result<(Vec<u8>, bool, bool)>{
+    match data.first(){
+        Some(&COMPACT_V1) => Ok((data[1..].to_vec(), false, true)),
+        Some(&COMPACT_V1_COMPRESSED) => Ok((decompress(&data[1..])?, true, true)),
+        Some(&COMPRESSED_PREFIX) => {
+            let dec = decode_config(&data[1..], URL_SAFE_NO_PAD)?;
+            Ok((hex::decode(decompress(&dec)?)?, true, false))
+        }
+        _ => Ok((hex::decode(decode_config(data, URL_SAFE_NO_PAD)?)?, false, false))
+    }
 }
 
 pub struct StreamsPacketBuilder<P>{
-    public: String,
-    masked: String,
+    public: Vec<u8>,
+    masked: Vec<u8>,
     _pub_marker: PhantomData<P>,
     key_nonce: Option<([u8;32], [u8;24])>,
+    compressed: bool,
+    compact: bool,
+    envelope: Option<PacketEnvelope>,
+    recipients: Vec<[u8;32]>,
+    ratchet_key: Option<(u64, [u8;32])>,
+    shreddable_key: Option<(String, [u8;32])>,
 }
 
 impl<P> StreamsPacketBuilder<P>
@@ -103,17 +412,23 @@ where
 {
     pub fn new() -> Self{
         StreamsPacketBuilder{
-            public: String::new(),
-            masked: String::new(),
+            public: vec![],
+            masked: vec![],
             _pub_marker: PhantomData,
-            key_nonce: None
+            key_nonce: None,
+            compressed: false,
+            compact: false,
+            envelope: None,
+            recipients: vec![],
+            ratchet_key: None,
+            shreddable_key: None
         }
     }
 
     pub fn public<T>(&mut self, data: &T) -> Result<&mut Self>
     where
         T: serde::Serialize{
-        self.public = P::serialize(data)?;
+        self.public = P::serialize_bytes(data)?;
         Ok(self)
     }
 
@@ -121,17 +436,86 @@ where
     where
         T: serde::Serialize
     {
-        self.masked = P::serialize(data)?;
+        self.masked = P::serialize_bytes(data)?;
         Ok(self)
     }
 
+    ///
+    /// Encrypt the masked data with the key. Each msg gets a fresh random nonce,
+    /// the specified one is used only to read the msgs encrypted with a fixed nonce
+    ///
     pub fn key_nonce(&mut self, key: &[u8;32], nonce: &[u8;24]) -> &mut Self{
         self.key_nonce = Some((key.clone(), nonce.clone()));
         self
     }
 
+    ///
+    /// Encrypt the masked data for the specified X25519 public keys. Only the recipients are able
+    /// to read it, the other readers get only the public data. It takes precedence over the key_nonce
+    ///
+    pub fn recipients(&mut self, recipients: &[[u8;32]]) -> &mut Self{
+        self.recipients = recipients.to_vec();
+        self
+    }
+
+    ///
+    /// Encrypt the masked data with the next key of the ratchet, moving it one step forward.
+    /// It takes precedence over the key_nonce
+    ///
+    pub fn ratchet(&mut self, ratchet: &mut KeyRatchet) -> &mut Self{
+        self.ratchet_key = Some(ratchet.next_key());
+        self
+    }
+
+    ///
+    /// Encrypt the masked data with a new random key kept in the store. Erasing the key from the store
+    /// makes the masked data permanently unreadable. It takes precedence over the key_nonce
+    ///
+    pub fn shreddable(&mut self, key_store: &mut KeyStore) -> &mut Self{
+        self.shreddable_key = Some(key_store.generate_key());
+        self
+    }
+
+    ///
+    /// Compress the public and masked data of the packet. The readers decompress them automatically
+    ///
+    #[cfg(feature = "compression")]
+    pub fn compressed(&mut self, compressed: bool) -> &mut Self{
+        self.compressed = compressed;
+        self
+    }
+
+    ///
+    /// Put the serialized data straight in the msg instead of the legacy hex and base64 encoding.
+    /// Readers of this library detect the encoding automatically
+    ///
+    pub fn compact(&mut self, compact: bool) -> &mut Self{
+        self.compact = compact;
+        self
+    }
+
+    ///
+    /// Write an envelope with the content type, the serializer id and the schema version at the beginning of the public payload
+    ///
+    pub fn envelope(&mut self, content_type: &str, schema_version: u32) -> &mut Self{
+        self.envelope = Some(PacketEnvelope::new(content_type, P::SERIALIZER_ID, schema_version));
+        self
+    }
+
     pub fn build(&mut self) -> StreamsPacket<P> {
-        StreamsPacket::new(&self.public.as_bytes(), &self.masked.as_bytes(), self.key_nonce.clone())
+        StreamsPacket{
+            p_data: self.public.clone(),
+            m_data: self.masked.clone(),
+            _marker: PhantomData,
+            key_nonce: self.key_nonce.clone(),
+            compressed: self.compressed,
+            compact: self.compact,
+            envelope: self.envelope.clone(),
+            recipients: self.recipients.clone(),
+            ratchet_key: self.ratchet_key,
+            shreddable_key: self.shreddable_key.clone(),
+            sealed: false
+        }
     }
 
 }
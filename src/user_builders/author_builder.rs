@@ -10,7 +10,8 @@ use iota_streams::app_channels::api::ChannelType;
 pub struct AuthorBuilder{
     seed: String,
     node_url: String,
-    send_options: SendOptions
+    send_options: SendOptions,
+    channel_type: ChannelType
 }
 
 impl AuthorBuilder{
@@ -21,7 +22,8 @@ impl AuthorBuilder{
         AuthorBuilder{
             seed: random_seed(),
             node_url: "https://api.lb-0.testnet.chrysalis2.com".to_string(),
-            send_options: send_opts
+            send_options: send_opts,
+            channel_type: ChannelType::SingleBranch
         }
     }
 
@@ -66,13 +68,18 @@ impl AuthorBuilder{
         self
     }
 
+    pub fn channel_type(mut self, channel_type: ChannelType) -> Self{
+        self.channel_type = channel_type;
+        self
+    }
+
     pub fn build(self) -> Author<StreamsClient>{
         let mut client = StreamsClient::new_from_url(&self.node_url);
         client.set_send_options(self.send_options);
 
         Author::new(
             &self.seed,
-            ChannelType::SingleBranch,
+            self.channel_type,
             client
         )
     }
@@ -2,14 +2,15 @@ use iota_streams::app::transport::{
     TransportOptions,
     tangle::client::{SendOptions, Client as StreamsClient}
 };
-use crate::utility::iota_utility::{random_seed, hash_string};
+use crate::utility::iota_utility::{random_seed, hash_string, create_psk};
 use iota_streams::app_channels::api::tangle::Subscriber;
 
 pub struct SubscriberBuilder{
     seed: String,
     node_url: String,
     encoding: String,
-    send_options: SendOptions
+    send_options: SendOptions,
+    psks: Vec<String>
 }
 
 impl SubscriberBuilder{
@@ -21,7 +22,8 @@ impl SubscriberBuilder{
             seed: random_seed(),
             node_url: "https://api.lb-0.testnet.chrysalis2.com".to_string(),
             encoding: "utf-8".to_string(),
-            send_options: send_opts
+            send_options: send_opts,
+            psks: vec![]
         }
     }
 
@@ -71,12 +73,26 @@ impl SubscriberBuilder{
         self
     }
 
+    pub fn psk(mut self, psk_seed: &str) -> Self{
+        self.psks.push(psk_seed.to_string());
+        self
+    }
+
+    pub fn psks(&self) -> &[String]{
+        &self.psks
+    }
+
     pub fn build(self) -> Subscriber<StreamsClient>{
         let mut client = StreamsClient::new_from_url(&self.node_url);
         client.set_send_options(self.send_options);
-        Subscriber::new(
+        let mut subscriber = Subscriber::new(
             &self.seed,
             client
-        )
+        );
+        for psk_seed in self.psks.iter(){
+            let (pskid, psk) = create_psk(psk_seed);
+            subscriber.store_psk(pskid, psk);
+        }
+        subscriber
     }
 }
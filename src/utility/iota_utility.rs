@@ -1,7 +1,8 @@
 use anyhow::Result;
 use iota_streams::core::prelude::hex;
 use rand::Rng;
-use iota_streams::app_channels::api::tangle::Address;
+use iota_streams::app_channels::api::tangle::{Address, PublicKey};
+use iota_streams::app_channels::api::{psk_from_seed, pskid_from_psk, Psk, PskId};
 use std::convert::TryInto;
 use chacha20poly1305::aead::{Aead, NewAead};
 use chacha20poly1305::aead::generic_array::GenericArray;
@@ -45,6 +46,30 @@ pub fn create_link(channel_address: &str, msg_id: &str) -> Result<Address>{
     }
 }
 
+pub fn public_key_to_hex(public_key: &PublicKey) -> String{
+    hex::encode(public_key.as_bytes())
+}
+
+pub fn public_key_from_hex(public_key: &str) -> Result<PublicKey>{
+    let bytes = hex::decode(public_key)?;
+    match PublicKey::from_bytes(&bytes){
+        Ok(pk) => Ok(pk),
+        Err(_) => Err(anyhow::Error::msg(format!("Invalid public key {}", public_key)))
+    }
+}
+
+///
+/// Derives a pre shared key and its id from a seed string
+///
+pub fn create_psk(psk_seed: &str) -> (PskId, Psk){
+    let psk = psk_from_seed(psk_seed.as_bytes());
+    (pskid_from_psk(&psk), psk)
+}
+
+pub fn pskid_to_hex(pskid: &PskId) -> String{
+    hex::encode(pskid)
+}
+
 pub fn create_encryption_key(string_key: &str) -> [u8; 32]{
     hash_string(string_key).as_bytes()[..32].try_into().unwrap()
 
@@ -74,8 +99,147 @@ pub fn encrypt_data(data: &[u8], key: &[u8; 32], nonce: &[u8; 24]) -> Result<Vec
     }
 }
 
+///
+/// Encrypts the data with a fresh random nonce, that is prepended to the ciphertext
+///
+pub fn encrypt_data_with_random_nonce(data: &[u8], key: &[u8; 32]) -> Result<Vec<u8>>{
+    let mut nonce = [0u8; 24];
+    rand::thread_rng().fill(&mut nonce[..]);
+    let enc = encrypt_data(data, key, &nonce)?;
+    Ok([&nonce[..], &enc].concat())
+}
+
+///
+/// Decrypts data whose nonce is prepended to the ciphertext
+///
+pub fn decrypt_data_with_prefixed_nonce(data: &[u8], key: &[u8; 32]) -> Result<Vec<u8>>{
+    if data.len() < 24{
+        return Err(anyhow::Error::msg("Error during data decryption"));
+    }
+    let nonce: [u8; 24] = data[..24].try_into().unwrap();
+    decrypt_data(&data[24..], key, &nonce)
+}
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &js_sys::Function, timeout: i32) -> i32;
+}
+
+///
+/// Waits for the specified milliseconds without blocking the js event loop
+///
+#[cfg(target_arch = "wasm32")]
+pub async fn sleep(millis: u32){
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        set_timeout(&resolve, millis as i32);
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn sleep(millis: u32){
+    Delay::new(millis).await
+}
+
+///
+/// Timer future that does not depend on a specific async runtime: the delay elapses on a separate
+/// thread, which wakes the task when it is done, so the executor is never blocked
+///
+#[cfg(not(target_arch = "wasm32"))]
+struct Delay{
+    state: std::sync::Arc<std::sync::Mutex<DelayState>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct DelayState{
+    elapsed: bool,
+    waker: Option<std::task::Waker>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Delay{
+    fn new(millis: u32) -> Delay{
+        let state = std::sync::Arc::new(std::sync::Mutex::new(DelayState{ elapsed: false, waker: None }));
+        let timer_state = state.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(millis as u64));
+            if let Ok(mut state) = timer_state.lock(){
+                state.elapsed = true;
+                if let Some(waker) = state.waker.take(){
+                    waker.wake();
+                }
+            }
+        });
+        Delay{ state }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl std::future::Future for Delay{
+    type Output = ();
+
+    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<()> {
+        let mut state = match self.state.lock(){
+            Ok(state) => state,
+            Err(_) => return std::task::Poll::Ready(())
+        };
+        match state.elapsed{
+            true => std::task::Poll::Ready(()),
+            false => {
+                state.waker = Some(cx.waker().clone());
+                std::task::Poll::Pending
+            }
+        }
+    }
+}
+
+///
+/// Gets the current unix time in seconds
+///
+#[cfg(target_arch = "wasm32")]
+pub fn current_timestamp() -> u64{
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn current_timestamp() -> u64{
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 pub fn msg_index(address: &Address) -> String{
     let total = [address.appinst.as_ref(), address.msgid.as_ref()].concat();
     let hash = Blake2b256::digest(&total);
     hex::encode(&hash)
 }
+
+///
+/// Prepends the branch name to a payload. The `#` separator never appears in url-safe base64,
+/// so tagged payloads can always be told apart from untagged ones
+///
+pub fn tag_branch_payload(branch: &str, payload: &[u8]) -> Vec<u8>{
+    [format!("#{}#", branch).as_bytes(), payload].concat()
+}
+
+///
+/// Splits a payload into its branch name, if any, and the original payload
+///
+pub fn untag_branch_payload(payload: &[u8]) -> (Option<String>, Vec<u8>){
+    if payload.first() != Some(&b'#'){
+        return (None, payload.to_vec());
+    }
+    match payload[1..].iter().position(|b| *b == b'#'){
+        None => (None, payload.to_vec()),
+        Some(end) => {
+            let branch = String::from_utf8_lossy(&payload[1..end + 1]).to_string();
+            (Some(branch), payload[end + 2..].to_vec())
+        }
+    }
+}